@@ -2,23 +2,34 @@
 //! [Amstrad CPC](https://en.wikipedia.org/wiki/Amstrad_CPC) `.cdt` tape data files, and converting
 //! encoded tape data to waveform sample data for conversion to WAV or direct playback.
 //!
-//! To parse a TZX/CDT data, use [`rtzx::TzxData::parse_from()`](crate::tzx::TzxData::parse_from).
+//! To parse a TZX/CDT data, use [`rtzx::TzxData::parse()`](crate::tzx::TzxData::parse).
 //!
 //! Example:
 //!
+//! ```no_run
+//! use rtzx::TzxData;
+//! use rtzx::tzx::ParseMode;
+//! use std::fs::File;
+//!
+//! let mut file = File::open("some-zx-spectrum-tape.tzx").expect("Couldn't open file");
+//! let (tzx_data, report) = TzxData::parse(&mut file, ParseMode::Lenient).expect("Couldn't parse file");
+//! for diagnostic in &report.diagnostics {
+//!     println!("Skipped {}", diagnostic);
+//! }
 //! ```
-//! let file = match File::open("some-zx-spectrum-tape.tzx") {
-//!     Err(why) => panic!("Couldn't open file: {}", why),
-//!     Ok(file) => file,
-//! };
-//! let tzx_data = TzxData::parse_from(file);
-//! ```
+//!
+//! Blocks that fail to parse are skipped and recorded in the returned
+//! [`ParseReport`](crate::tzx::ParseReport). Use [`ParseMode::Strict`](crate::tzx::ParseMode::Strict)
+//! to fail on the first such block instead.
 //!
 //! The [`rtzx::TzxData`](crate::tzx::TzxData) struct contains two fields,
 //! the [`.header`](crate::tzx::TzxData::header) for specifying the TZX version, and
 //! [`.blocks`](crate::tzx::TzxData::blocks), which a vector containing all blocks parsed from the
 //! TZX data.
 //!
+//! To construct TZX data programmatically, e.g. for generating test tapes or custom loaders, use
+//! [`rtzx::TzxData::builder()`](crate::tzx::TzxData::builder).
+//!
 //! Parsing a TZX/CDT file does not require configuration, however conversion / playback will need
 //! [`rtzx::Config`](crate::tzx::Config) to set the sample rate, playback speed, etc.
 //!
@@ -29,6 +40,14 @@
 //! (e.g. [`rtzx::ui::commands::convert::run_convert`](crate::ui::commands::run_convert))
 //! or used as [Source](https://docs.rs/rodio/latest/rodio/source/trait.Source.html)s for playback
 //! with [Rodio](https://docs.rs/rodio/latest/rodio/)
+//!
+//! Emulators and analysis tools needing exact timings rather than samples can instead use
+//! [`rtzx::TzxData::pulses()`](crate::tzx::TzxData::pulses), which iterates the pulses of the whole
+//! tape as lengths in ZX Spectrum t cycles, following loops, jumps and call sequences. To use a
+//! tape as the input of an emulator, wrap it in a [`rtzx::tzx::TapeDeck`](crate::tzx::TapeDeck)
+//! and advance it by CPU t cycles to obtain the EAR level. Standard blocks can also be fast loaded
+//! by trapping the Spectrum ROM loader with
+//! [`.load_rom_block()`](crate::tzx::TapeDeck::load_rom_block).
 
 pub mod tzx;
 pub mod ui;
@@ -1,13 +1,18 @@
 use clap::Parser;
 use std::fs::File;
 use std::io;
+use std::process::exit;
 
 use rtzx::{TapeDataFile, TapeDataFileType};
 use rtzx::ui::commands::{
     Commands,
     convert::run_convert,
+    edit::run_edit,
+    merge::run_merge,
+    optimise::run_optimise,
     inspect::run_inspect,
     play::run_play,
+    split::run_split,
 };
 
 /// rtzx: A utility for interacting with TZX / CDT tape files.
@@ -21,12 +26,26 @@ struct Cli {
 fn main() -> io::Result<()> {
     let cli = Cli::parse();
 
+    let parse_mode = cli.command.as_ref().map(|cmd| cmd.parse_mode()).unwrap_or_default();
+
+    // Merge reads multiple files, so is handled separately
+    if let Some(Commands::Merge(args)) = &cli.command {
+        if let Err(why) = run_merge(args, parse_mode) {
+            eprintln!("{}", why);
+            exit(1);
+        }
+        return Ok(());
+    }
+
     // Create a path to the desired file
     let file_name = &cli.command.as_ref().and_then(|cmd| cmd.file_name()).expect("Filename not supplied");
 
     // Open the path in read-only mode, returns `io::Result<File>`
     let mut file = match File::open(file_name) {
-        Err(why) => panic!("Couldn't open {:?}: {}", file_name, why),
+        Err(why) => {
+            eprintln!("Couldn't open {:?}: {}", file_name, why);
+            exit(1);
+        }
         Ok(file) => file,
     };
 
@@ -34,15 +53,45 @@ fn main() -> io::Result<()> {
 
     let file_type = TapeDataFileType::from(file_name.extension().and_then(|s| s.to_str()));
 
-    let file_data = match TapeDataFile::read_as(&mut file, file_type) {
-        Err(why) => panic!("Failed to parse {:?} as {}: {}", file_name, file_type, why),
-        Ok(data) => data,
+    let file_data = match TapeDataFile::parse_as(&mut file, file_type, parse_mode) {
+        Err(why) => {
+            eprintln!("Failed to parse {:?} as {}: {}", file_name, file_type, why);
+            exit(1);
+        }
+        Ok((data, report)) => {
+            for diagnostic in &report.diagnostics {
+                eprintln!("Warning: {} {}", diagnostic.outcome(), diagnostic);
+            }
+            // Writing over the input would lose the skipped blocks for good. Recovered damaged regions are written back
+            // as they were read, so are safe.
+            if report.has_skipped() && cli.command.as_ref().is_some_and(|cmd| cmd.overwrites_input()) {
+                eprintln!("Refusing to overwrite {:?} as blocks were skipped while parsing: use -o to write to another file", file_name);
+                exit(1);
+            }
+            data
+        }
     };
 
-    return match &cli.command {
+    let block_count = file_data.tzx_data.as_ref().map_or(0, |tzx_data| tzx_data.blocks.len());
+    if let Some(Err(why)) = cli.command.as_ref().map(|cmd| cmd.check_blocks(block_count)) {
+        eprintln!("{}", why);
+        exit(1);
+    }
+
+    let result = match &cli.command {
         Some(Commands::Inspect(args)) => run_inspect(file_name, &config, args.waveforms, &file_data),
         Some(Commands::Convert(args)) => run_convert(&args, &config, &file_data),
+        Some(Commands::Edit(command)) => run_edit(command, &file_data),
+        Some(Commands::Optimise(args)) => run_optimise(args, &file_data),
         Some(Commands::Play(_)) => run_play(file_name, &config, &file_data),
-        None => Ok(()),
+        Some(Commands::Split(args)) => run_split(args, &file_data),
+        Some(Commands::Merge(_)) | None => Ok(()),
     };
+
+    if let Err(why) = result {
+        eprintln!("{}", why);
+        exit(1);
+    }
+
+    return Ok(());
 }
@@ -1,20 +1,31 @@
 pub mod blocks;
+pub mod builder;
 pub mod config;
 pub mod data;
 pub mod header;
+pub mod optimise;
+pub mod output;
+pub mod parse_report;
 pub mod tap;
+pub mod timing;
+pub mod tape_deck;
 pub mod tzx_data;
 pub mod platform;
 pub mod player;
+pub mod pulses;
 pub mod recovery_enum;
 pub mod waveforms;
 
+pub use builder::TzxDataBuilder;
 pub use config::Config;
 pub use header::Header;
+pub use parse_report::{ParseDiagnostic, ParseError, ParseMode, ParseReport};
 pub use tap::TapData;
-pub use tzx_data::TzxData;
+pub use tape_deck::{TapeDeck, TapeDeckEvent};
+pub use tzx_data::{SplitPoints, TzxData};
 pub use platform::Platform;
-pub use player::Player;
+pub use player::{BlockGroup, Player};
+pub use pulses::TapePulses;
 pub use recovery_enum::RecoveryEnum;
 
 use binrw::BinResult;
@@ -86,15 +97,28 @@ pub struct TapeDataFile {
 }
 
 impl TapeDataFile {
+    /// Parses tape data of the given file type, skipping any blocks that fail to parse.
     pub fn read_as<R: Read + Seek>(reader: &mut R, file_type: TapeDataFileType) -> BinResult<Self> {
+        TapeDataFile::parse_as(reader, file_type, ParseMode::Lenient)
+            .map(|(tape_data, _)| tape_data)
+            .map_err(Into::into)
+    }
+
+    /// Parses tape data of the given file type, returning a [ParseReport] of any problems encountered alongside the
+    /// data. See [TzxData::parse] and [TapData::parse] for how the [ParseMode] is applied.
+    pub fn parse_as<R: Read + Seek>(
+        reader: &mut R,
+        file_type: TapeDataFileType,
+        mode: ParseMode,
+    ) -> Result<(Self, ParseReport), ParseError> {
         match file_type {
             TapeDataFileType::Cdt | TapeDataFileType::Tsx | TapeDataFileType::Tzx => {
-                let tzx_data = TzxData::read(reader)?;
-                Ok(TapeDataFile { file_type, tzx_data: Some(tzx_data), tap_data: None })
+                let (tzx_data, report) = TzxData::parse(reader, mode)?;
+                Ok((TapeDataFile { file_type, tzx_data: Some(tzx_data), tap_data: None }, report))
             }
             TapeDataFileType::Tap => {
-                let tap_data = TapData::read(reader)?;
-                Ok(TapeDataFile { file_type, tzx_data: Some(tap_data.clone().into()), tap_data: Some(tap_data) })
+                let (tap_data, report) = TapData::parse(reader, mode)?;
+                Ok((TapeDataFile { file_type, tzx_data: Some(tap_data.clone().into()), tap_data: Some(tap_data) }, report))
             }
         }
     }
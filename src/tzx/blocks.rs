@@ -43,13 +43,14 @@ pub use select_block::{SelectBlock, SelectBlockEntry};
 pub use set_signal_level::SetSignalLevel;
 pub use snapshot_block::SnapshotBlock;
 pub use standard_speed_data_block::StandardSpeedDataBlock;
-pub use turbo_speed_data_block::TurboSpeedDataBlock;
+pub use turbo_speed_data_block::{TurboSpeedDataBlock, TurboSpeedTimings};
 pub use text_description::{TextDescription, MessageBlock};
 
 use crate::tzx::{
     Config,
     ExtendedDisplayCollector,
     RecoveryEnum,
+    pulses::PulseStream,
     waveforms::{EmptyWaveform, Waveform}
 };
 
@@ -91,6 +92,13 @@ pub trait Block: fmt::Display + Any {
     /// from one loop iteration to the next.
     fn next_block_start_pulse_high(&self, _config: Arc<Config>, self_start_pulse_high: bool) -> bool { self_start_pulse_high }
 
+    /// Returns the pulses of the block, including any pause following it, for a given starting pulse state.
+    ///
+    /// Unlike [.get_waveforms()](Block::get_waveforms), pulse lengths are exact counts of ZX Spectrum t cycles and are
+    /// independent of the [`Config`]. Blocks that produce no signal, such as informational and flow control blocks,
+    /// return no pulses.
+    fn pulses(&self, _start_pulse_high: bool) -> PulseStream { Box::new(std::iter::empty()) }
+
     /// Returns a boxed dyn clone of the block.
     fn clone_box(&self) -> Box<dyn Block>;
 
@@ -187,6 +195,38 @@ impl Block for UnsupportedBlockTypeBlock {
     fn as_any_mut(&mut self) -> &mut dyn Any { self }
 }
 
+/// Holds a region of raw data that could not be parsed as blocks, as retained by [ParseMode::Recover].
+///
+/// Damaged regions are kept so that the extent of any damage can be inspected, and are written back out as TZX data
+/// unchanged, starting with the block type of the block that failed to parse.
+///
+/// [ParseMode::Recover]: crate::tzx::ParseMode::Recover
+#[derive(Clone, Debug)]
+pub struct DamagedRegion {
+    /// The offset of the start of the region in the data.
+    pub offset: u64,
+    pub payload: Vec<u8>
+}
+
+impl fmt::Display for DamagedRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DamagedRegion: {:5} bytes at offset {:#x}", self.payload.len(), self.offset)
+    }
+}
+
+impl Block for DamagedRegion {
+    fn r#type(&self) -> BlockType {
+        return BlockType::Undefined;
+    }
+
+    fn clone_box(&self) -> Box<dyn Block> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
 /// A TZX [Glue Block](https://worldofspectrum.net/TZXformat.html#GLUEBLOCK).
 ///
 /// This block type allows TZX/CDT files to be concatenated.
@@ -274,6 +314,7 @@ block_ref_enum! {
     CustomInfoBlock => CustomInfoBlock,
     SnapshotBlock => SnapshotBlock,
     InstructionsBlock => InstructionsBlock,
+    KansasCityStandardDataBlock => KansasCityStandardDataBlock,
     GlueBlock => GlueBlock,
     UndefinedBlockTypeBlock => UndefinedBlockTypeBlock,
     UnsupportedBlockTypeBlock => UnsupportedBlockTypeBlock,
@@ -327,8 +368,103 @@ pub fn read_block(block_type: RecoveryEnum<BlockType, u8>, mut reader: impl Read
     }
 }
 
+/// Returns the bytes of the supplied text truncated to the given maximum length, for use by blocks encoding text
+/// with a length prefix.
+pub(crate) fn truncated_text(text: &str, max_length: usize) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    bytes[..std::cmp::min(bytes.len(), max_length)].to_vec()
+}
+
+/// Returns the number of leading entries that fit in a block with an entry count of at most `max_count` and a 16 bit
+/// length, where the length covers `fixed_length` bytes as well as the entries, for use by blocks that drop entries
+/// that cannot be encoded rather than wrapping the count or length.
+pub(crate) fn fitting_entry_count<T>(
+    entries: &[T],
+    max_count: usize,
+    fixed_length: usize,
+    entry_length: impl Fn(&T) -> usize
+) -> usize {
+    let mut length = fixed_length;
+    return entries.iter()
+        .take(max_count)
+        .take_while(|entry| {
+            length += entry_length(entry);
+            return length <= u16::MAX as usize;
+        })
+        .count();
+}
+
 fn to_box_dyn<T>(block_result: BinResult<T>) -> Result<Box<dyn Block>, Error>
 where T: Block + 'static
 {
     block_result.map(|block| -> Box<dyn Block> { Box::new(block) })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tzx::{ParseMode, TzxData, blocks::generalized_data_block::{SymbolDefinition, SymbolPolarity}};
+    use crate::tzx::blocks::hardware_type::{HardwareInformation, HardwareType};
+    use std::io::Cursor;
+
+    /// Writes the block followed by a pure tone, and reads it back strictly, so that a count or length that does not
+    /// match the entries leaves the pure tone misread.
+    fn round_trip(block: impl Block + 'static) -> TzxData {
+        let tzx_data = TzxData::builder().block(block).pure_tone(1000, 100).build();
+        let mut bytes = Cursor::new(Vec::new());
+        tzx_data.write(&mut bytes).unwrap();
+        let (tzx_data, _) = TzxData::parse(&mut Cursor::new(bytes.into_inner()), ParseMode::Strict).unwrap();
+        assert_eq!(tzx_data.blocks.len(), 2);
+        assert!(tzx_data.blocks[1].as_any().is::<PureTone>());
+        return tzx_data;
+    }
+
+    fn archive_info_entries(count: usize, text: &str) -> Vec<ArchiveInfoEntry> {
+        return (0..count).map(|_| ArchiveInfoEntry::new(ArchiveInfoEntryType::Comment, text)).collect();
+    }
+
+    fn archive_info_entry_count(tzx_data: &TzxData) -> usize {
+        return tzx_data.blocks[0].as_any().downcast_ref::<ArchiveInfo>().unwrap().entries().len();
+    }
+
+    #[test]
+    fn archive_info_drops_entries_beyond_the_count_limit() {
+        assert_eq!(archive_info_entry_count(&round_trip(ArchiveInfo::new(archive_info_entries(255, "A")))), 255);
+        assert_eq!(archive_info_entry_count(&round_trip(ArchiveInfo::new(archive_info_entries(256, "A")))), 255);
+    }
+
+    #[test]
+    fn archive_info_drops_entries_beyond_the_length_limit() {
+        // Each entry takes 257 bytes, so 255 of them would need a length of 65536.
+        let text = "A".repeat(255);
+        assert_eq!(archive_info_entry_count(&round_trip(ArchiveInfo::new(archive_info_entries(255, &text)))), 254);
+    }
+
+    #[test]
+    fn select_blocks_drop_entries_beyond_the_limits() {
+        round_trip(SelectBlock::new((0..256).map(|_| SelectBlockEntry::new(1, "A")).collect()));
+        // Each entry takes 258 bytes, so 254 of them would need a length of 65533 and 255 a length of 65791.
+        round_trip(SelectBlock::new((0..255).map(|_| SelectBlockEntry::new(1, &"A".repeat(255))).collect()));
+    }
+
+    #[test]
+    fn hardware_type_blocks_drop_entries_beyond_the_count_limit() {
+        let entry = HardwareTypeBlockEntry::new(HardwareType::Computer(RecoveryEnum::Unknown(0)), HardwareInformation::Runs);
+        round_trip(HardwareTypeBlock::new(vec![entry; 256]));
+    }
+
+    #[test]
+    fn call_sequences_drop_offsets_beyond_the_count_limit() {
+        round_trip(CallSequence::new(&[1; 65536]));
+    }
+
+    #[test]
+    fn generalized_data_blocks_drop_symbols_and_pulses_beyond_the_limits() {
+        // A key can refer to 256 symbols, which are encoded with an alphabet size of zero.
+        let symbols: Vec<SymbolDefinition> = (0..257)
+            .map(|_| SymbolDefinition::with_pulses(SymbolPolarity::Opposite, &[855; 256]))
+            .collect();
+        let data = GeneralizedDataBlock::pack_data_symbols(256, &[0, 255]);
+        round_trip(GeneralizedDataBlock::new(0, vec![], vec![], symbols, 2, data));
+    }
+}
@@ -5,7 +5,7 @@ use binrw::{
 use std::any::Any;
 use std::fmt;
 use strum_macros::Display;
-use crate::tzx::blocks::{Block, BlockType};
+use crate::tzx::blocks::{Block, BlockType, fitting_entry_count, truncated_text};
 use crate::tzx::{ExtendedDisplayCollector, RecoveryEnum};
 
 /// An [Archive info](https://worldofspectrum.net/TZXformat.html#ARCHINFO) block.
@@ -21,6 +21,23 @@ pub struct ArchiveInfo {
     entries: Vec<ArchiveInfoEntry>
 }
 
+impl ArchiveInfo {
+    /// Creates a new [ArchiveInfo] block from the supplied entries, calculating the block length. Entries beyond the
+    /// 255 that a block can hold, or that would take the block length past 65535 bytes, are dropped.
+    pub fn new(mut entries: Vec<ArchiveInfoEntry>) -> Self {
+        entries.truncate(fitting_entry_count(&entries, u8::MAX as usize, 1, |entry| 2 + entry.text.len()));
+        let length = 1 + entries.iter().map(|entry| 2 + entry.text.len()).sum::<usize>();
+        Self {
+            length: length as u16,
+            entry_count: entries.len() as u8,
+            entries,
+        }
+    }
+
+    /// Returns the archive info entries.
+    pub fn entries(&self) -> &[ArchiveInfoEntry] { &self.entries }
+}
+
 impl fmt::Display for ArchiveInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "ArchiveInfo: {} entries", self.entry_count)
@@ -77,6 +94,29 @@ pub struct ArchiveInfoEntry {
     text: Vec<u8>
 }
 
+impl ArchiveInfoEntry {
+    /// Creates a new [ArchiveInfoEntry]. Text longer than 255 bytes is truncated.
+    pub fn new(entry_type: ArchiveInfoEntryType, text: &str) -> Self {
+        let text = truncated_text(text, u8::MAX as usize);
+        Self {
+            entry_type: RecoveryEnum::Known(entry_type),
+            length: text.len() as u8,
+            text,
+        }
+    }
+
+    /// Returns the entry type, if known.
+    pub fn entry_type(&self) -> Option<ArchiveInfoEntryType> {
+        match self.entry_type {
+            RecoveryEnum::Known(entry_type) => Some(entry_type),
+            RecoveryEnum::Unknown(_) => None,
+        }
+    }
+
+    /// Returns the entry text.
+    pub fn text(&self) -> String { String::from_utf8_lossy(&self.text).to_string() }
+}
+
 impl fmt::Display for ArchiveInfoEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let description = String::from_utf8_lossy(&self.text);
@@ -7,7 +7,7 @@ use crate::tzx::blocks::Block;
 use crate::tzx::blocks::BlockType;
 
 /// A [Call sequence](https://worldofspectrum.net/TZXformat.html#CALLSEQ) block.
-/// Parsed, but unsupported.
+/// Followed by [TzxData::pulses](crate::tzx::TzxData::pulses), but unsupported for playback.
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
@@ -17,6 +17,21 @@ pub struct CallSequence {
     block_offsets: Vec<i16>,
 }
 
+impl CallSequence {
+    /// Creates a new [CallSequence] with the given relative block offsets. Offsets beyond the 65535 that a block can
+    /// hold are dropped.
+    pub fn new(block_offsets: &[i16]) -> Self {
+        let block_offsets = &block_offsets[..block_offsets.len().min(u16::MAX as usize)];
+        Self {
+            length: block_offsets.len() as u16,
+            block_offsets: block_offsets.to_vec(),
+        }
+    }
+
+    /// Returns the relative block offsets of the blocks to call.
+    pub fn block_offsets(&self) -> &[i16] { &self.block_offsets }
+}
+
 impl fmt::Display for CallSequence {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "CallSequence: {} blocks", self.length)
@@ -37,13 +52,21 @@ impl Block for CallSequence {
 }
 
 /// A [Return from sequence](https://worldofspectrum.net/TZXformat.html#RETURNSEQ) block.
-/// Parsed, but not supported.
+/// Followed by [TzxData::pulses](crate::tzx::TzxData::pulses), but not supported for playback.
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
 pub struct ReturnFromSequence {
 }
 
+impl ReturnFromSequence {
+    pub fn new() -> Self { Self {} }
+}
+
+impl Default for ReturnFromSequence {
+    fn default() -> Self { Self::new() }
+}
+
 impl fmt::Display for ReturnFromSequence {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "ReturnFromSequence")
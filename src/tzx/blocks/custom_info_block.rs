@@ -21,6 +21,19 @@ pub struct CustomInfoBlock {
     data: Vec<u8>,
 }
 
+impl CustomInfoBlock {
+    /// Creates a new [CustomInfoBlock]. The identification string is truncated or padded with spaces to 16 bytes.
+    pub fn new(id: &str, data: Vec<u8>) -> Self {
+        let mut id = id.as_bytes().to_vec();
+        id.resize(16, b' ');
+        Self {
+            id,
+            length: data.len() as u32,
+            data,
+        }
+    }
+}
+
 impl fmt::Display for CustomInfoBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let id_string = String::from_utf8_lossy(&self.id);
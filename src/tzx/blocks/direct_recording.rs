@@ -10,6 +10,7 @@ use crate::tzx::{
     Config,
     blocks::{Block, BlockType},
     data::DataPayload,
+    pulses::{self, PulseStream},
     waveforms::{
         DirectWaveform,
         PauseType,
@@ -35,6 +36,28 @@ pub struct DirectRecording {
     payload: DataPayload,
 }
 
+impl DirectRecording {
+    pub fn new(length_sample: u16, pause: u16, payload: DataPayload) -> Self {
+        Self {
+            length_sample,
+            pause,
+            payload,
+        }
+    }
+
+    /// Returns the length of each sample in ZX Spectrum t cycles.
+    pub fn length_sample(&self) -> u16 { self.length_sample }
+
+    /// Returns the pause after the block in milliseconds.
+    pub fn pause(&self) -> u16 { self.pause }
+
+    /// Sets the pause after the block in milliseconds.
+    pub fn set_pause(&mut self, pause: u16) { self.pause = pause }
+
+    /// Returns the sample data.
+    pub fn payload(&self) -> &DataPayload { &self.payload }
+}
+
 impl fmt::Display for DirectRecording {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "DirectRecording: {:5} bytes, pause {:5}ms (length_sample: {}; used_bits: {})",
@@ -75,6 +98,16 @@ impl Block for DirectRecording {
         return !direct_source.last_pulse_high();
     }
 
+    fn pulses(&self, _start_pulse_high: bool) -> PulseStream {
+        let direct = if self.payload.total_bits() > 0 {
+            DirectWaveform::new(pulses::pulse_config(), self.length_sample, self.payload.clone()).pulse_lengths()
+        } else {
+            Vec::new()
+        };
+
+        return pulses::with_pause(direct.into_iter(), self.pause);
+    }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
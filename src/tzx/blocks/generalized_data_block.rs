@@ -10,13 +10,15 @@ use std::sync::Arc;
 use crate::tzx::{
     Config,
     ExtendedDisplayCollector,
-    blocks::{Block, BlockType},
+    blocks::{Block, BlockType, TurboSpeedDataBlock, TurboSpeedTimings},
     data::DataPayload,
+    pulses::{self, PulseStream},
     waveforms::{
         GeneralizedWaveform,
         PauseType,
         PauseWaveform,
         Waveform,
+        generalized_waveform::GeneralizedPulseIterator,
     },
 };
 
@@ -64,6 +66,11 @@ pub struct SymbolDefinition {
 
 impl SymbolDefinition {
     pub fn new() -> Self { SymbolDefinition::default() }
+
+    /// Creates a symbol definition with the given first pulse polarity and pulse lengths.
+    pub fn with_pulses(polarity: SymbolPolarity, pulses: &[u16]) -> Self {
+        SymbolDefinition { polarity, pulses: pulses.to_vec() }
+    }
 }
 
 impl fmt::Display for SymbolDefinition {
@@ -193,15 +200,239 @@ impl fmt::Display for GeneralizedDataBlock {
 }
 
 impl GeneralizedDataBlock {
+    /// Creates a new [GeneralizedDataBlock], calculating the block length and table sizes.
+    ///
+    /// `totd` is the number of symbols encoded in the packed `data` stream (see
+    /// [pack_data_symbols](GeneralizedDataBlock::pack_data_symbols)). Symbol definitions are padded with zero length
+    /// pulses to the length of the longest definition in each table. Symbol tables are omitted when the corresponding
+    /// stream is empty, as required by the specification.
+    ///
+    /// Symbol keys are bytes, so symbols beyond the 256 that a key can refer to are dropped from each table, and
+    /// pulses beyond the 255 that a symbol definition can hold are dropped from each symbol.
+    pub fn new(
+        pause: u16,
+        symbols_pilot: Vec<SymbolDefinition>,
+        pilot_data: Vec<PilotRLE>,
+        symbols_data: Vec<SymbolDefinition>,
+        totd: u32,
+        data: Vec<u8>,
+    ) -> Self {
+        let totp = pilot_data.len() as u32;
+        let symbols_pilot = if totp > 0 { symbols_pilot } else { vec![] };
+        let symbols_data = if totd > 0 { symbols_data } else { vec![] };
+        let (npp, symbols_pilot) = Self::pad_symbols(symbols_pilot);
+        let (npd, symbols_data) = Self::pad_symbols(symbols_data);
+
+        let length = 14
+            + symbols_pilot.len() * (npp as usize * 2 + 1)
+            + totp as usize * 3
+            + symbols_data.len() * (npd as usize * 2 + 1)
+            + data.len();
+
+        Self {
+            length: length as u32,
+            pause,
+            totp,
+            npp,
+            // An alphabet size of 256 is encoded as zero.
+            asp: symbols_pilot.len() as u8,
+            totd,
+            npd,
+            asd: symbols_data.len() as u8,
+            symbols_pilot,
+            pilot_data: Arc::new(pilot_data),
+            symbols_data,
+            data: Arc::new(data),
+        }
+    }
+
+    /// Returns the pause after the block in milliseconds.
+    pub fn pause(&self) -> u16 { self.pause }
+
+    /// Sets the pause after the block in milliseconds.
+    pub fn set_pause(&mut self, pause: u16) { self.pause = pause }
+
+    /// Packs a sequence of data symbol keys into a data stream for an alphabet of the given size.
+    ///
+    /// Each key occupies `ceil(log2(alphabet_size))` bits, most significant bit first, as per the specification.
+    pub fn pack_data_symbols(alphabet_size: usize, keys: &[u8]) -> Vec<u8> {
+        let key_bits = Self::symbol_key_bits(alphabet_size);
+        let mut data = bitvec![u8, Msb0;];
+        for key in keys {
+            data.extend(&key.view_bits::<Msb0>()[(8 - key_bits)..8]);
+        }
+        data.into_vec()
+    }
+
+    /// Returns the number of bits used to encode a symbol key for an alphabet of the given size.
+    pub fn symbol_key_bits(alphabet_size: usize) -> usize {
+        std::cmp::max(1, alphabet_size.next_power_of_two().ilog2() as usize)
+    }
+
+    fn pad_symbols(mut symbols: Vec<SymbolDefinition>) -> (u8, Vec<SymbolDefinition>) {
+        symbols.truncate(u8::MAX as usize + 1);
+        let max_pulses = symbols.iter().map(|symbol| symbol.pulses.len()).max().unwrap_or(0).min(u8::MAX as usize);
+        for symbol in symbols.iter_mut() {
+            symbol.pulses.resize(max_pulses, 0);
+        }
+        (max_pulses as u8, symbols)
+    }
+
     pub fn pilot_data_payload(&self) -> DataPayload {
+        let key_bits = Self::symbol_key_bits(self.symbols_pilot.len());
         let mut data = bitvec![u8, Msb0;];
         for entry in self.pilot_data.as_slice() {
             let symbol_bits = entry.symbol.view_bits::<Msb0>();
             for _ in 0..entry.repetitions {
-                data.extend(&symbol_bits[(8 - self.symbols_pilot.len().ilog2() as usize)..8]);
+                data.extend(&symbol_bits[(8 - key_bits)..8]);
+            }
+        }
+        let used_bits = match data.len() % 8 { 0 => 8, remainder => remainder };
+        DataPayload::new(used_bits as u8, Arc::new(data.into_vec()))
+    }
+
+    /// Returns the pilot and data waveforms where present, with the data following on from the pilot.
+    fn generalized_waveforms(&self, config: Arc<Config>, start_pulse_high: bool) -> (Option<GeneralizedWaveform>, Option<GeneralizedWaveform>) {
+        let pilot_source = if self.totp > 0 {
+            Some(GeneralizedWaveform::new(
+                config.clone(),
+                Arc::new(self.symbols_pilot.clone()),
+                self.pilot_data_payload(),
+                start_pulse_high,
+            ).pilot())
+        } else {
+            None
+        };
+
+        // The level preceding the data is that of the last pilot pulse, with the polarity of the first data symbol
+        // applied to it. For the usual opposite polarity the first data pulse therefore changes level, rather than
+        // extending the last pilot pulse.
+        let data_source = if self.totd > 0 {
+            Some(GeneralizedWaveform::new(
+                config.clone(),
+                Arc::new(self.symbols_data.clone()),
+                self.data_payload(),
+                pilot_source.as_ref().map_or(start_pulse_high, |pilot_source| !pilot_source.last_pulse_high()),
+            ).following(pilot_source.as_ref().map_or(0, |pilot_source| pilot_source.t_cycles())))
+        } else {
+            None
+        };
+
+        return (pilot_source, data_source);
+    }
+
+    /// Returns the data stream as a payload, with the used bits of the last byte given by the number of symbols.
+    fn data_payload(&self) -> DataPayload {
+        let data_bits = Self::symbol_key_bits(self.symbols_data.len()) * self.totd as usize;
+        let used_bits = match data_bits % 8 { 0 => 8, remainder => remainder };
+        DataPayload::new(used_bits as u8, self.data.clone())
+    }
+}
+
+/// Converts a [TurboSpeedDataBlock] to an equivalent [GeneralizedDataBlock].
+///
+/// The pilot tone and sync pulses are encoded as two pilot symbols, a one pulse pilot symbol repeated for the length
+/// of the pilot tone followed by a two pulse sync symbol, and the data is encoded using a two pulse symbol for each
+/// of zero and one, with the payload used directly as the data stream. Both blocks produce identical pulse streams,
+/// and the conversion can be reversed with [TryFrom]. Where the payload is empty, the data symbols are omitted as
+/// required by the specification, so the zero and one pulse lengths are lost and the conversion cannot be reversed.
+///
+/// ```
+/// use rtzx::tzx::blocks::{GeneralizedDataBlock, TurboSpeedDataBlock, TurboSpeedTimings};
+///
+/// let turbo = TurboSpeedDataBlock::with_timings(TurboSpeedTimings::default(), 1000, vec![0xff, 0x00].into());
+/// let generalized = GeneralizedDataBlock::from(&turbo);
+/// let round_trip = TurboSpeedDataBlock::try_from(&generalized).unwrap();
+/// assert_eq!(round_trip.timings(), turbo.timings());
+/// ```
+impl From<&TurboSpeedDataBlock> for GeneralizedDataBlock {
+    fn from(block: &TurboSpeedDataBlock) -> Self {
+        let symbols_pilot = vec![
+            SymbolDefinition::with_pulses(SymbolPolarity::Opposite, &[block.length_pulse_pilot]),
+            SymbolDefinition::with_pulses(SymbolPolarity::Opposite, &[block.length_pulse_sync_first, block.length_pulse_sync_second]),
+        ];
+        let pilot_data = vec![PilotRLE::new(0, block.length_tone_pilot), PilotRLE::new(1, 1)];
+        let symbols_data = vec![
+            SymbolDefinition::with_pulses(SymbolPolarity::Opposite, &[block.length_pulse_zero, block.length_pulse_zero]),
+            SymbolDefinition::with_pulses(SymbolPolarity::Opposite, &[block.length_pulse_one, block.length_pulse_one]),
+        ];
+        let totd = if block.payload.data.is_empty() { 0 } else { block.payload.total_bits() as u32 };
+
+        GeneralizedDataBlock::new(block.pause, symbols_pilot, pilot_data, symbols_data, totd, block.payload.data.to_vec())
+    }
+}
+
+/// Converts a [GeneralizedDataBlock] to an equivalent [TurboSpeedDataBlock], where possible.
+///
+/// The pilot stream must consist of a single pulse pilot symbol repeated up to 65535 times, followed by either a
+/// single two pulse sync symbol or two single pulse sync symbols. The data alphabet must consist of two symbols of
+/// two equal length pulses, for zero and one respectively. All symbols must have opposite polarity, i.e. continue
+/// the alternation of pulses.
+impl TryFrom<&GeneralizedDataBlock> for TurboSpeedDataBlock {
+    type Error = &'static str;
+
+    fn try_from(block: &GeneralizedDataBlock) -> Result<Self, Self::Error> {
+        let symbol_pulses = |symbols: &[SymbolDefinition], key: u8| -> Result<Vec<u16>, Self::Error> {
+            let symbol = symbols.get(key as usize).ok_or("symbol key out of range")?;
+            if symbol.polarity != SymbolPolarity::Opposite {
+                return Err("symbol polarity is not opposite");
+            }
+            Ok(symbol.pulses.iter().copied().take_while(|length| *length != 0).collect())
+        };
+
+        // Combine pilot entries repeating the same symbol.
+        let mut pilot_runs: Vec<(u8, u32)> = Vec::new();
+        for entry in block.pilot_data.iter() {
+            match pilot_runs.last_mut() {
+                Some((symbol, repetitions)) if *symbol == entry.symbol => *repetitions += entry.repetitions as u32,
+                _ => pilot_runs.push((entry.symbol, entry.repetitions as u32)),
             }
         }
-        DataPayload::new((8 - data.len() % self.symbols_pilot.len().ilog2() as usize) as u8, Arc::new(data.into_vec()))
+
+        let (pilot, sync) = match pilot_runs.as_slice() {
+            [pilot, sync @ ..] if !sync.is_empty() => (Some(*pilot), sync),
+            sync => (None, sync),
+        };
+        let (length_pulse_pilot, length_tone_pilot) = match pilot {
+            Some((symbol, repetitions)) => match symbol_pulses(&block.symbols_pilot, symbol)?.as_slice() {
+                [length] => (*length, u16::try_from(repetitions).map_err(|_| "pilot tone is too long")?),
+                _ => return Err("pilot symbol does not have a single pulse"),
+            },
+            None => (0, 0),
+        };
+
+        let sync_pulses = match sync {
+            [(symbol, 1)] => symbol_pulses(&block.symbols_pilot, *symbol)?,
+            [(first, 1), (second, 1)] => [symbol_pulses(&block.symbols_pilot, *first)?, symbol_pulses(&block.symbols_pilot, *second)?].concat(),
+            _ => return Err("pilot stream does not end with two sync pulses"),
+        };
+        let [length_pulse_sync_first, length_pulse_sync_second] = sync_pulses[..] else {
+            return Err("pilot stream does not end with two sync pulses");
+        };
+
+        if block.symbols_data.len() != 2 {
+            return Err("data alphabet does not have two symbols");
+        }
+        let [length_pulse_zero, length_pulse_one] = [0, 1].map(|key| match symbol_pulses(&block.symbols_data, key).as_deref() {
+            Ok([first, second]) if first == second => Ok(*first),
+            Ok(_) => Err("data symbol does not have two equal pulses"),
+            Err(why) => Err(*why),
+        });
+        let (length_pulse_zero, length_pulse_one) = (length_pulse_zero?, length_pulse_one?);
+
+        if block.data.len() != (block.totd as usize).div_ceil(8) {
+            return Err("data stream length does not match the number of data symbols");
+        }
+
+        let timings = TurboSpeedTimings {
+            length_pulse_pilot,
+            length_pulse_sync_first,
+            length_pulse_sync_second,
+            length_pulse_zero,
+            length_pulse_one,
+            length_tone_pilot,
+        };
+        return Ok(TurboSpeedDataBlock::with_timings(timings, block.pause, block.data_payload()));
     }
 }
 
@@ -211,35 +442,57 @@ impl Block for GeneralizedDataBlock {
     }
 
     fn get_waveforms(&self, config: Arc<Config>, start_pulse_high: bool) -> Vec<Box<dyn Waveform + Send>> {
-        let mut pilot_source: Option<GeneralizedWaveform> = None;
-        if self.totp > 0 {
-            let pilot_payload = self.pilot_data_payload();
-            pilot_source = Some(GeneralizedWaveform::new(
-                config.clone(),
-                Arc::new(self.symbols_pilot.clone()),
-                pilot_payload,
-                start_pulse_high,
-            ));
-        }
-
-        let data_used_bits = 8 - (self.data.len() * 8).saturating_sub(self.symbols_data.len().ilog2() as usize * self.totd as usize) as u8;
-        let data_payload = DataPayload::new(data_used_bits, self.data.clone());
-        let data_source = GeneralizedWaveform::new(
-            config.clone(),
-            Arc::new(self.symbols_data.clone()),
-            data_payload,
-            if self.totp > 0 { pilot_source.clone().unwrap().last_pulse_high() } else { start_pulse_high },
-        );
+        let (pilot_source, data_source) = self.generalized_waveforms(config.clone(), start_pulse_high);
         let pause_source = PauseWaveform::new(config.clone(), self.pause, PauseType::Zero);
 
-        if self.totp > 0 {
-            return vec![Box::new(pilot_source.unwrap()), Box::new(data_source), Box::new(pause_source)];
-        } else {
-            return vec![Box::new(data_source), Box::new(pause_source)];
+        let mut waveforms: Vec<Box<dyn Waveform + Send>> = Vec::new();
+        if let Some(pilot_source) = pilot_source {
+            waveforms.push(Box::new(pilot_source));
+        }
+        if let Some(data_source) = data_source {
+            waveforms.push(Box::new(data_source));
+        }
+        waveforms.push(Box::new(pause_source));
+        return waveforms;
+    }
+
+    fn next_block_start_pulse_high(&self, config: Arc<Config>, self_start_pulse_high: bool) -> bool {
+        if self.pause > 0 {
+            return true;
         }
+
+        let (pilot_source, data_source) = self.generalized_waveforms(config, self_start_pulse_high);
+        return match data_source.or(pilot_source) {
+            Some(source) => !source.last_pulse_high(),
+            None => self_start_pulse_high,
+        };
     }
 
-    fn next_block_start_pulse_high(&self, _config: Arc<Config>, _self_start_pulse_high: bool) -> bool { true }
+    fn pulses(&self, start_pulse_high: bool) -> PulseStream {
+        let pilot: Vec<(u32, bool)> = if self.totp > 0 {
+            pulses::from_pulses(GeneralizedPulseIterator::new(
+                pulses::pulse_config(),
+                Arc::new(self.symbols_pilot.clone()),
+                self.pilot_data_payload(),
+                start_pulse_high,
+            )).collect()
+        } else {
+            Vec::new()
+        };
+        let data_start_pulse_high = pilot.last().map_or(start_pulse_high, |(_, high)| !high);
+        let data: PulseStream = if self.totd > 0 {
+            Box::new(pulses::from_pulses(GeneralizedPulseIterator::new(
+                pulses::pulse_config(),
+                Arc::new(self.symbols_data.clone()),
+                self.data_payload(),
+                data_start_pulse_high,
+            )))
+        } else {
+            Box::new(std::iter::empty())
+        };
+
+        return pulses::with_pause(pilot.into_iter().chain(data), self.pause);
+    }
 
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
@@ -260,3 +513,141 @@ impl Block for GeneralizedDataBlock {
     fn as_any(&self) -> &dyn Any { self }
     fn as_any_mut(&mut self) -> &mut dyn Any { self }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tzx::TzxData;
+
+    /// A linear congruential generator, so that failures are reproducible.
+    struct Random(u32);
+
+    impl Random {
+        fn below(&mut self, max: u32) -> u32 {
+            self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+            (self.0 >> 8) % max
+        }
+
+        fn pulse(&mut self) -> u16 { 200 + self.below(3000) as u16 }
+
+        fn bytes(&mut self, count: u32) -> Vec<u8> { (0..count).map(|_| self.below(256) as u8).collect() }
+    }
+
+    fn assert_identical_pulses(a: &dyn Block, b: &dyn Block) {
+        let config = Arc::new(Config::builder().build());
+        for start_pulse_high in [false, true] {
+            assert_eq!(a.pulses(start_pulse_high).collect::<Vec<_>>(), b.pulses(start_pulse_high).collect::<Vec<_>>());
+            assert_eq!(
+                a.next_block_start_pulse_high(config.clone(), start_pulse_high),
+                b.next_block_start_pulse_high(config.clone(), start_pulse_high),
+            );
+        }
+    }
+
+    #[test]
+    fn turbo_speed_data_blocks_convert_to_identical_pulses() {
+        let mut random = Random(1);
+        for _ in 0..200 {
+            let timings = TurboSpeedTimings {
+                length_pulse_pilot: random.pulse(),
+                length_pulse_sync_first: random.pulse(),
+                length_pulse_sync_second: random.pulse(),
+                length_pulse_zero: random.pulse(),
+                length_pulse_one: random.pulse(),
+                length_tone_pilot: 1 + random.below(300) as u16,
+            };
+            let length = 1 + random.below(32);
+            let data = random.bytes(length);
+            let payload = DataPayload::new(1 + random.below(8) as u8, Arc::new(data));
+            let turbo = TurboSpeedDataBlock::with_timings(timings, random.below(2) as u16 * 1000, payload);
+
+            let generalized = GeneralizedDataBlock::from(&turbo);
+            assert_identical_pulses(&turbo, &generalized);
+
+            let round_trip = TurboSpeedDataBlock::try_from(&generalized).unwrap();
+            assert_eq!(round_trip.timings(), turbo.timings());
+            assert_eq!(round_trip.pause, turbo.pause);
+            assert_eq!(round_trip.payload.data, turbo.payload.data);
+            assert_eq!(round_trip.payload.used_bits, turbo.payload.used_bits);
+        }
+    }
+
+    #[test]
+    fn generalized_data_blocks_convert_to_identical_pulses() {
+        let mut random = Random(2);
+        for _ in 0..200 {
+            let opposite = |pulses: &[u16]| SymbolDefinition::with_pulses(SymbolPolarity::Opposite, pulses);
+            let (sync_first, sync_second) = (random.pulse(), random.pulse());
+
+            // The sync pulses may be given as one symbol of two pulses, or as two symbols of one pulse.
+            let (symbols_pilot, pilot_data) = if random.below(2) == 0 {
+                (vec![opposite(&[random.pulse()]), opposite(&[sync_first, sync_second])], vec![PilotRLE::new(1, 1)])
+            } else {
+                (
+                    vec![opposite(&[random.pulse()]), opposite(&[sync_first]), opposite(&[sync_second])],
+                    vec![PilotRLE::new(1, 1), PilotRLE::new(2, 1)],
+                )
+            };
+            let pilot_data = [vec![PilotRLE::new(0, 1 + random.below(300) as u16)], pilot_data].concat();
+
+            let (zero, one) = (random.pulse(), random.pulse());
+            let symbols_data = vec![opposite(&[zero, zero]), opposite(&[one, one])];
+            let length = 1 + random.below(200);
+            let keys: Vec<u8> = (0..length).map(|_| random.below(2) as u8).collect();
+            let data = GeneralizedDataBlock::pack_data_symbols(2, &keys);
+            let generalized = GeneralizedDataBlock::new(
+                random.below(2) as u16 * 1000, symbols_pilot, pilot_data, symbols_data, keys.len() as u32, data,
+            );
+
+            let turbo = TurboSpeedDataBlock::try_from(&generalized).unwrap();
+            assert_identical_pulses(&generalized, &turbo);
+        }
+    }
+
+    #[test]
+    fn data_changes_level_after_the_pilot() {
+        let opposite = |pulses: &[u16]| SymbolDefinition::with_pulses(SymbolPolarity::Opposite, pulses);
+        let data = GeneralizedDataBlock::pack_data_symbols(2, &[0, 1]);
+        let generalized = GeneralizedDataBlock::new(
+            0, vec![opposite(&[1000])], vec![PilotRLE::new(0, 3)], vec![opposite(&[500, 500]), opposite(&[700, 700])], 2, data,
+        );
+
+        let pulses: Vec<(u32, bool)> = generalized.pulses(true).collect();
+        assert_eq!(pulses, [(1000, true), (1000, false), (1000, true), (500, false), (500, true), (700, false), (700, true)]);
+
+        // The played samples follow the same levels as the pulses, as for the equivalent turbo speed data block.
+        let config = Arc::new(Config::builder().build());
+        let turbo = TurboSpeedDataBlock::with_timings(TurboSpeedTimings::default(), 0, vec![0x5a; 4].into());
+        let samples = |block: &dyn Block| -> Vec<f32> { block.get_waveforms(config.clone(), true).into_iter().flatten().collect() };
+        assert_eq!(samples(&turbo), samples(&GeneralizedDataBlock::from(&turbo)));
+    }
+
+    #[test]
+    fn other_alphabets_are_not_converted() {
+        let opposite = |pulses: &[u16]| SymbolDefinition::with_pulses(SymbolPolarity::Opposite, pulses);
+        let symbols_pilot = vec![opposite(&[2168]), opposite(&[667, 735])];
+        let pilot_data = vec![PilotRLE::new(0, 3223), PilotRLE::new(1, 1)];
+        let symbols_data = vec![opposite(&[855, 855]), opposite(&[1710, 1710]), opposite(&[3420, 3420])];
+        let data = GeneralizedDataBlock::pack_data_symbols(3, &[0, 1, 2]);
+        let generalized = GeneralizedDataBlock::new(1000, symbols_pilot, pilot_data, symbols_data, 3, data);
+
+        assert!(TurboSpeedDataBlock::try_from(&generalized).is_err());
+    }
+
+    #[test]
+    fn blocks_without_data_produce_no_data_waveform() {
+        let config = Arc::new(Config::builder().build());
+        let turbo = TurboSpeedDataBlock::with_timings(TurboSpeedTimings::default(), 1000, DataPayload::new(8, Arc::new(vec![])));
+        let tzx_data = TzxData::builder()
+            .block(GeneralizedDataBlock::from(&turbo))
+            .generalized_block(1000, vec![], vec![], vec![SymbolDefinition::with_pulses(SymbolPolarity::Opposite, &[855, 855])], &[])
+            .build();
+
+        // The pilot and sync come out as one generalized waveform followed by the pause, then a pause waveform alone.
+        let waveform_counts: Vec<usize> = tzx_data.blocks.iter()
+            .map(|block| block.get_waveforms(config.clone(), false).len())
+            .collect();
+        assert_eq!(waveform_counts, [2, 1]);
+        assert!(tzx_data.blocks[1].next_block_start_pulse_high(config.clone(), false));
+    }
+}
@@ -3,8 +3,7 @@ use binrw::{
 };
 use std::any::Any;
 use std::fmt;
-use crate::tzx::blocks::Block;
-use crate::tzx::blocks::BlockType;
+use crate::tzx::blocks::{Block, BlockType, truncated_text};
 
 /// A [Group start](https://worldofspectrum.net/TZXformat.html#GRPSTART) block.
 /// Passively supported, in that it doesn't really do anything.
@@ -17,6 +16,17 @@ pub struct GroupStart {
     text: Vec<u8>
 }
 
+impl GroupStart {
+    /// Creates a new [GroupStart] with the given group name. Names longer than 255 bytes are truncated.
+    pub fn new(name: &str) -> Self {
+        let text = truncated_text(name, u8::MAX as usize);
+        Self { length: text.len() as u8, text }
+    }
+
+    /// Returns the group name.
+    pub fn name(&self) -> String { String::from_utf8_lossy(&self.text).to_string() }
+}
+
 impl fmt::Display for GroupStart {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let description = String::from_utf8_lossy(&self.text);
@@ -45,6 +55,14 @@ impl Block for GroupStart {
 pub struct GroupEnd {
 }
 
+impl GroupEnd {
+    pub fn new() -> Self { Self {} }
+}
+
+impl Default for GroupEnd {
+    fn default() -> Self { Self::new() }
+}
+
 impl fmt::Display for GroupEnd {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "GroupEnd")
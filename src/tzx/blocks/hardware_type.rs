@@ -59,6 +59,18 @@ pub struct HardwareTypeBlock {
     entries: Vec<HardwareTypeBlockEntry>
 }
 
+impl HardwareTypeBlock {
+    /// Creates a new [HardwareTypeBlock] from the supplied entries. Entries beyond the 255 that a block can hold are
+    /// dropped.
+    pub fn new(mut entries: Vec<HardwareTypeBlockEntry>) -> Self {
+        entries.truncate(u8::MAX as usize);
+        Self {
+            entry_count: entries.len() as u8,
+            entries,
+        }
+    }
+}
+
 impl fmt::Display for HardwareTypeBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "HardwareTypeBlock: {} entries", self.entry_count)
@@ -264,6 +276,16 @@ pub struct HardwareTypeBlockEntry {
     information: RecoveryEnum<HardwareInformation, u8>,
 }
 
+impl HardwareTypeBlockEntry {
+    pub fn new(hardware_type: HardwareType, information: HardwareInformation) -> Self {
+        Self {
+            hardware_type: RecoveryEnum::Known(hardware_type),
+            unknown_hardware_id: 0,
+            information: RecoveryEnum::Known(information),
+        }
+    }
+}
+
 impl fmt::Display for HardwareTypeBlockEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}: {}", self.hardware_type, self.information)
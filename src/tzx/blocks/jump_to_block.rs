@@ -7,7 +7,7 @@ use crate::tzx::blocks::Block;
 use crate::tzx::blocks::BlockType;
 
 /// A [Jump to block](https://worldofspectrum.net/TZXformat.html#JUMPBLOCK) block.
-/// Parsed, but currently unsupported.
+/// Followed by [TzxData::pulses](crate::tzx::TzxData::pulses), but currently unsupported for playback.
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
@@ -15,6 +15,14 @@ pub struct JumpToBlock {
     offset: i16,
 }
 
+impl JumpToBlock {
+    /// Creates a new [JumpToBlock] with the given relative block offset.
+    pub fn new(offset: i16) -> Self { Self { offset } }
+
+    /// Returns the relative block offset.
+    pub fn offset(&self) -> i16 { self.offset }
+}
+
 impl fmt::Display for JumpToBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "JumpToBlock: {}", self.offset)
@@ -11,12 +11,14 @@ use crate::tzx::{
     ExtendedDisplayCollector,
     blocks::{Block, BlockType},
     data::DataPayload,
+    pulses::{self, PulseStream},
     waveforms::{
         KansasCityStandardDataWaveform,
         PauseType,
         PauseWaveform,
         PilotWaveform,
         Waveform,
+        kansas_city_standard_data_waveform::KCSDataPulseIterator,
     },
 };
 
@@ -188,7 +190,7 @@ impl Block for KansasCityStandardDataBlock {
             self.bit_byte_config.clone(),
             self.payload.clone(),
             if self.length_tone_pilot % 2 == 0 { start_pulse_high } else { !start_pulse_high },
-        );
+        ).following(self.length_pulse_pilot as u64 * self.length_tone_pilot as u64);
         let pause_source = PauseWaveform::new(config.clone(), self.pause, PauseType::Zero);
 
         return vec![Box::new(pilot_source), Box::new(data_source), Box::new(pause_source)];
@@ -202,6 +204,20 @@ impl Block for KansasCityStandardDataBlock {
         return if self.length_tone_pilot % 2 == 0 { self_start_pulse_high } else { !self_start_pulse_high };
     }
 
+    fn pulses(&self, start_pulse_high: bool) -> PulseStream {
+        let pilot = pulses::tone(self.length_pulse_pilot, self.length_tone_pilot, start_pulse_high);
+        let data = pulses::from_pulses(KCSDataPulseIterator::new(
+            pulses::pulse_config(),
+            self.length_pulse_zero,
+            self.length_pulse_one,
+            self.bit_byte_config,
+            self.payload.clone(),
+            if self.length_tone_pilot.is_multiple_of(2) { start_pulse_high } else { !start_pulse_high },
+        ));
+
+        return pulses::with_pause(pilot.chain(data), self.pause);
+    }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
@@ -7,7 +7,7 @@ use crate::tzx::blocks::Block;
 use crate::tzx::blocks::BlockType;
 
 /// A [Loop start](https://worldofspectrum.net/TZXformat.html#LOOPSTART) block.
-/// Parsed, but currently unsupported.
+/// Followed by [TzxData::pulses](crate::tzx::TzxData::pulses), but currently unsupported for playback.
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
@@ -15,6 +15,13 @@ pub struct LoopStart {
     repititions: u16,
 }
 
+impl LoopStart {
+    pub fn new(repititions: u16) -> Self { Self { repititions } }
+
+    /// Returns the number of times the loop is played.
+    pub fn repetitions(&self) -> u16 { self.repititions }
+}
+
 impl fmt::Display for LoopStart {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "LoopStart: {} repititions", self.repititions)
@@ -35,13 +42,21 @@ impl Block for LoopStart {
 }
 
 /// A [Loop end](https://worldofspectrum.net/TZXformat.html#LOOPEND) block.
-/// Parsed, but currently unsupported.
+/// Followed by [TzxData::pulses](crate::tzx::TzxData::pulses), but currently unsupported for playback.
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
 pub struct LoopEnd {
 }
 
+impl LoopEnd {
+    pub fn new() -> Self { Self {} }
+}
+
+impl Default for LoopEnd {
+    fn default() -> Self { Self::new() }
+}
+
 impl fmt::Display for LoopEnd {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "LoopEnd")
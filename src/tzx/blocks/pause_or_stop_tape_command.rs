@@ -8,6 +8,7 @@ use::std::sync::Arc;
 use crate::tzx::{
     Config,
     blocks::{Block, BlockType},
+    pulses::{self, PulseStream},
     waveforms::{
         PauseType,
         PauseWaveform,
@@ -16,8 +17,8 @@ use crate::tzx::{
 };
 
 /// A [Pause or stop tape command](https://worldofspectrum.net/TZXformat.html#PAUSEBLOCK).
-/// At present, the length 0 'stop tape' instruction is not respected (TODO: implement this as an auto-pause
-/// in [Player](crate::tzx::Player)).
+/// At present, the length 0 'stop tape' instruction is only respected by [TapeDeck](crate::tzx::TapeDeck) (TODO:
+/// implement this as an auto-pause in [Player](crate::tzx::Player)).
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
@@ -25,6 +26,20 @@ pub struct PauseOrStopTapeCommand {
     pause: u16,
 }
 
+impl PauseOrStopTapeCommand {
+    /// Creates a new pause of the given length in milliseconds. A length of zero indicates a 'stop tape' command.
+    pub fn new(pause: u16) -> Self { Self { pause } }
+
+    /// Returns the length of the pause in milliseconds.
+    pub fn pause(&self) -> u16 { self.pause }
+
+    /// Sets the length of the pause in milliseconds. A length of zero makes this a 'stop tape' command.
+    pub fn set_pause(&mut self, pause: u16) { self.pause = pause }
+
+    /// Returns true if this is a 'stop tape' command, i.e. the pause length is zero.
+    pub fn is_stop_tape(&self) -> bool { self.pause == 0 }
+}
+
 impl fmt::Display for PauseOrStopTapeCommand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "PauseOrStopTapeCommand: pause {}ms", self.pause)
@@ -41,6 +56,8 @@ impl Block for PauseOrStopTapeCommand {
         return vec![Box::new(pause_source)];
     }
 
+    fn pulses(&self, _start_pulse_high: bool) -> PulseStream { pulses::pause(self.pause) }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
@@ -50,8 +67,8 @@ impl Block for PauseOrStopTapeCommand {
 }
 
 /// A [Stop tape if in 48k mode](https://worldofspectrum.net/TZXformat.html#STOP48K) block.
-/// Parsed, but unsupported. Potentially support for this could be added to [Player](crate::tzx::Player)
-/// using a configuration option.
+/// Respected by [TapeDeck](crate::tzx::TapeDeck) in 48K mode, but unsupported by [Player](crate::tzx::Player).
+/// Potentially support for this could be added to the player using a configuration option.
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
@@ -61,6 +78,14 @@ pub struct StopTapeIf48K {
     pub payload: Vec<u8>
 }
 
+impl StopTapeIf48K {
+    pub fn new() -> Self { Self { length: 0, payload: Vec::new() } }
+}
+
+impl Default for StopTapeIf48K {
+    fn default() -> Self { Self::new() }
+}
+
 impl fmt::Display for StopTapeIf48K {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "StopTapeIf48K")
@@ -8,6 +8,7 @@ use std::sync::Arc;
 use crate::tzx::{
     Config,
     blocks::{Block, BlockType},
+    pulses::{self, PulseStream},
     waveforms::{
         PulseSequenceWaveform,
         Waveform,
@@ -24,6 +25,22 @@ pub struct PulseSequence {
     pulse_lengths: Vec<u16>,
 }
 
+impl PulseSequence {
+    /// Creates a new [PulseSequence] from the supplied pulse lengths.
+    ///
+    /// A pulse sequence block can hold at most 255 pulses: any further pulses are ignored.
+    pub fn new(pulse_lengths: &[u16]) -> Self {
+        let length = std::cmp::min(pulse_lengths.len(), u8::MAX as usize);
+        Self {
+            length: length as u8,
+            pulse_lengths: pulse_lengths[..length].to_vec(),
+        }
+    }
+
+    /// Returns the lengths of the pulses in the sequence.
+    pub fn pulse_lengths(&self) -> &[u16] { &self.pulse_lengths }
+}
+
 impl fmt::Display for PulseSequence {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "PulseSequence: {} pulses",
@@ -63,6 +80,10 @@ impl Block for PulseSequence {
         return if self.length % 2 == 0 { self_start_pulse_high } else { !self_start_pulse_high };
     }
 
+    fn pulses(&self, start_pulse_high: bool) -> PulseStream {
+        return Box::new(pulses::sequence(self.pulse_lengths.clone(), start_pulse_high));
+    }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
@@ -11,11 +11,13 @@ use crate::tzx::{
     ExtendedDisplayCollector,
     blocks::{Block, BlockType},
     data::DataPayload,
+    pulses::{self, PulseStream},
     waveforms::{
         DataWaveform,
         PauseType,
         PauseWaveform,
         Waveform,
+        data_waveform::DataPulseIterator,
     },
 };
 
@@ -37,6 +39,23 @@ pub struct PureDataBlock {
     payload: DataPayload,
 }
 
+impl PureDataBlock {
+    pub fn new(length_pulse_zero: u16, length_pulse_one: u16, pause: u16, payload: DataPayload) -> Self {
+        Self {
+            length_pulse_zero,
+            length_pulse_one,
+            pause,
+            payload,
+        }
+    }
+
+    /// Returns the pause after the block in milliseconds.
+    pub fn pause(&self) -> u16 { self.pause }
+
+    /// Sets the pause after the block in milliseconds.
+    pub fn set_pause(&mut self, pause: u16) { self.pause = pause }
+}
+
 impl fmt::Display for PureDataBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "PureDataBlock: {:5} bytes, pause {:5}ms (0/1: {}/{}; used_bits: {})",
@@ -71,6 +90,18 @@ impl Block for PureDataBlock {
         return self.pause > 0 || self_start_pulse_high;
     }
 
+    fn pulses(&self, start_pulse_high: bool) -> PulseStream {
+        let data = pulses::from_pulses(DataPulseIterator::new(
+            pulses::pulse_config(),
+            self.length_pulse_zero,
+            self.length_pulse_one,
+            self.payload.clone(),
+            start_pulse_high,
+        ));
+
+        return pulses::with_pause(data, self.pause);
+    }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
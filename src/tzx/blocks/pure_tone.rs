@@ -8,6 +8,7 @@ use std::sync::Arc;
 use crate::tzx::{
     Config,
     blocks::{Block, BlockType},
+    pulses::{self, PulseStream},
     waveforms::{
         PilotWaveform,
         Waveform,
@@ -23,6 +24,15 @@ pub struct PureTone {
     length_tone: u16,
 }
 
+impl PureTone {
+    pub fn new(length_pulse: u16, length_tone: u16) -> Self {
+        Self {
+            length_pulse,
+            length_tone,
+        }
+    }
+}
+
 impl fmt::Display for PureTone {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "PureTone: {}*{}",
@@ -52,6 +62,10 @@ impl Block for PureTone {
         return if self.length_tone % 2 == 0 { self_start_pulse_high } else { !self_start_pulse_high };
     }
 
+    fn pulses(&self, start_pulse_high: bool) -> PulseStream {
+        return Box::new(pulses::tone(self.length_pulse, self.length_tone, start_pulse_high));
+    }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
@@ -5,7 +5,7 @@ use std::any::Any;
 use std::fmt;
 use crate::tzx::{
     ExtendedDisplayCollector,
-    blocks::{Block, BlockType}
+    blocks::{Block, BlockType, fitting_entry_count, truncated_text}
 };
 
 /// A [Select](https://worldofspectrum.net/TZXformat.html#SELBLOCK) block.
@@ -22,6 +22,20 @@ pub struct SelectBlock {
     entries: Vec<SelectBlockEntry>
 }
 
+impl SelectBlock {
+    /// Creates a new [SelectBlock] from the supplied entries, calculating the block length. Entries beyond the 255
+    /// that a block can hold, or that would take the block length past 65535 bytes, are dropped.
+    pub fn new(mut entries: Vec<SelectBlockEntry>) -> Self {
+        entries.truncate(fitting_entry_count(&entries, u8::MAX as usize, 1, |entry| 3 + entry.text.len()));
+        let length = 1 + entries.iter().map(|entry| 3 + entry.text.len()).sum::<usize>();
+        Self {
+            length: length as u16,
+            entry_count: entries.len() as u8,
+            entries,
+        }
+    }
+}
+
 impl fmt::Display for SelectBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "SelectBlock: {} selections", self.entry_count)
@@ -57,6 +71,15 @@ pub struct SelectBlockEntry {
     text: Vec<u8>
 }
 
+impl SelectBlockEntry {
+    /// Creates a new [SelectBlockEntry] with the given relative block offset and description. Descriptions longer
+    /// than 255 bytes are truncated.
+    pub fn new(offset: i16, text: &str) -> Self {
+        let text = truncated_text(text, u8::MAX as usize);
+        Self { offset, length: text.len() as u8, text }
+    }
+}
+
 impl fmt::Display for SelectBlockEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let description = String::from_utf8_lossy(&self.text);
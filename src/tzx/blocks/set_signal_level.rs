@@ -23,6 +23,16 @@ pub struct SetSignalLevel {
     payload: Vec<u8>,
 }
 
+impl SetSignalLevel {
+    pub fn new(high: bool) -> Self {
+        Self {
+            length: 1,
+            signal_level: if high { 1 } else { 0 },
+            payload: Vec::new(),
+        }
+    }
+}
+
 impl fmt::Display for SetSignalLevel {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "SetSignalLevel: {}", if self.signal_level == 0 { "low" } else { "high"})
@@ -10,6 +10,7 @@ use crate::tzx::{
     ExtendedDisplayCollector,
     blocks::{Block, BlockType},
     data::DataPayload,
+    pulses::{self, PulseStream},
     waveforms::{
         DataWaveform,
         PauseType,
@@ -17,6 +18,7 @@ use crate::tzx::{
         PilotWaveform,
         SyncWaveform,
         Waveform,
+        data_waveform::DataPulseIterator,
     },
 };
 
@@ -39,9 +41,14 @@ pub struct StandardSpeedDataBlock {
 
 impl StandardSpeedDataBlock {
     pub fn new() -> Self {
+        Self::with_payload(2000, DataPayload::default())
+    }
+
+    /// Creates a new [StandardSpeedDataBlock] with the given pause and payload.
+    pub fn with_payload(pause: u16, payload: DataPayload) -> Self {
         Self {
-            pause: 2000,
-            payload: DataPayload::default(),
+            pause,
+            payload,
         }
     }
 }
@@ -63,10 +70,11 @@ impl Block for StandardSpeedDataBlock {
 
     fn get_waveforms(&self, config: Arc<Config>, start_pulse_high: bool) -> Vec<Box<dyn Waveform + Send>> {
         let header = self.payload.data[0] < 128;
+        let length_tone_pilot: u16 = if header { 8063 } else { 3223 };
         let pilot_source = PilotWaveform::new(
             config.clone(),
             2168,
-            if header { 8063 } else { 3223 },
+            length_tone_pilot,
             start_pulse_high,
         );
         let sync_pulses_source = SyncWaveform::new(
@@ -74,14 +82,14 @@ impl Block for StandardSpeedDataBlock {
             667,
             735,
             !start_pulse_high,
-        );
+        ).following(2168 * length_tone_pilot as u64);
         let data_source = DataWaveform::new(
             config.clone(),
             855,
             1710,
             self.payload.clone(),
             !start_pulse_high,
-        );
+        ).following(2168 * length_tone_pilot as u64 + 667 + 735);
         let pause_source = PauseWaveform::new(config.clone(), self.pause, PauseType::StartLow);
 
         return vec![Box::new(pilot_source), Box::new(sync_pulses_source), Box::new(data_source), Box::new(pause_source)];
@@ -89,6 +97,15 @@ impl Block for StandardSpeedDataBlock {
 
     fn next_block_start_pulse_high(&self, _config: Arc<Config>, _self_start_pulse_high: bool) -> bool { !self.pause > 0 }
 
+    fn pulses(&self, start_pulse_high: bool) -> PulseStream {
+        let header = self.payload.data.first().is_some_and(|flag| *flag < 128);
+        let pilot = pulses::tone(2168, if header { 8063 } else { 3223 }, start_pulse_high);
+        let sync = pulses::sequence(vec![667, 735], !start_pulse_high);
+        let data = pulses::from_pulses(DataPulseIterator::new(pulses::pulse_config(), 855, 1710, self.payload.clone(), !start_pulse_high));
+
+        return pulses::with_pause(pilot.chain(sync).chain(data), self.pause);
+    }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
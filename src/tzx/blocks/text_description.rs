@@ -3,8 +3,7 @@ use binrw::{
 };
 use std::any::Any;
 use std::fmt;
-use crate::tzx::blocks::Block;
-use crate::tzx::blocks::BlockType;
+use crate::tzx::blocks::{Block, BlockType, truncated_text};
 
 // A [Text description](https://worldofspectrum.net/TZXformat.html#TEXTDESCR) block.
 #[binrw]
@@ -16,6 +15,17 @@ pub struct TextDescription {
     text: Vec<u8>
 }
 
+impl TextDescription {
+    /// Creates a new [TextDescription]. Text longer than 255 bytes is truncated.
+    pub fn new(text: &str) -> Self {
+        let text = truncated_text(text, u8::MAX as usize);
+        Self { length: text.len() as u8, text }
+    }
+
+    /// Returns the description text.
+    pub fn text(&self) -> String { String::from_utf8_lossy(&self.text).to_string() }
+}
+
 impl fmt::Display for TextDescription {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let description = String::from_utf8_lossy(&self.text);
@@ -37,8 +47,8 @@ impl Block for TextDescription {
 }
 
 /// A [Message block](https://worldofspectrum.net/features/TZXformat.html#MSGBLOCK).
-/// Parsed, but unsupported beyond display of the message during playback / inspection. The display time is shown
-/// but not respected.
+/// The message is shown as a popup during playback for the display time, or until a key is pressed if the display
+/// time is 0.
 #[binrw]
 #[brw(little)]
 #[derive(Clone, Debug)]
@@ -49,6 +59,21 @@ pub struct MessageBlock {
     text: Vec<u8>
 }
 
+impl MessageBlock {
+    /// Creates a new [MessageBlock] to be displayed for the given number of seconds. Text longer than 255 bytes is
+    /// truncated.
+    pub fn new(display_for_secs: u8, text: &str) -> Self {
+        let text = truncated_text(text, u8::MAX as usize);
+        Self { display_for_secs, length: text.len() as u8, text }
+    }
+
+    /// Returns the number of seconds for which the message should be displayed, where 0 means until a key is pressed.
+    pub fn display_for_secs(&self) -> u8 { self.display_for_secs }
+
+    /// Returns the message text.
+    pub fn text(&self) -> String { String::from_utf8_lossy(&self.text).to_string() }
+}
+
 impl fmt::Display for MessageBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let description = String::from_utf8_lossy(&self.text);
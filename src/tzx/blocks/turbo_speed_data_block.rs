@@ -11,6 +11,7 @@ use crate::tzx::{
     ExtendedDisplayCollector,
     blocks::{Block, BlockType},
     data::DataPayload,
+    pulses::{self, PulseStream},
     waveforms::{
         DataWaveform,
         PauseType,
@@ -18,9 +19,49 @@ use crate::tzx::{
         PilotWaveform,
         SyncWaveform,
         Waveform,
+        data_waveform::DataPulseIterator,
     },
 };
 
+/// Pilot, sync and data pulse timings for a [TurboSpeedDataBlock], measured in ZX Spectrum t cycles.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct TurboSpeedTimings {
+    pub length_pulse_pilot: u16,
+    pub length_pulse_sync_first: u16,
+    pub length_pulse_sync_second: u16,
+    pub length_pulse_zero: u16,
+    pub length_pulse_one: u16,
+    pub length_tone_pilot: u16,
+}
+
+impl TurboSpeedTimings {
+    /// Returns the standard ZX Spectrum ROM timings, as used by
+    /// [StandardSpeedDataBlock](crate::tzx::blocks::StandardSpeedDataBlock).
+    pub fn spectrum_standard(header: bool) -> Self {
+        Self {
+            length_pulse_pilot: 2168,
+            length_pulse_sync_first: 667,
+            length_pulse_sync_second: 735,
+            length_pulse_zero: 855,
+            length_pulse_one: 1710,
+            length_tone_pilot: if header { 8063 } else { 3223 },
+        }
+    }
+}
+
+impl Default for TurboSpeedTimings {
+    fn default() -> Self {
+        Self {
+            length_pulse_pilot: 2370,
+            length_pulse_sync_first: 1185,
+            length_pulse_sync_second: 1185,
+            length_pulse_zero: 1185,
+            length_pulse_one: 1185,
+            length_tone_pilot: 4096,
+        }
+    }
+}
+
 /// A [Turbo Speed Data Block](https://worldofspectrum.net/TZXformat.html#TURBOSPEED).
 ///
 /// This is the most commonly used block type in CDT files, and is also commonly used in TZX files for programs
@@ -48,15 +89,32 @@ pub struct TurboSpeedDataBlock {
 
 impl TurboSpeedDataBlock {
     pub fn new() -> Self {
+        Self::with_timings(TurboSpeedTimings::default(), 2000, DataPayload::default())
+    }
+
+    /// Creates a new [TurboSpeedDataBlock] with the given timings, pause and payload.
+    pub fn with_timings(timings: TurboSpeedTimings, pause: u16, payload: DataPayload) -> Self {
         Self {
-            length_pulse_pilot: 2370,
-            length_pulse_sync_first: 1185,
-            length_pulse_sync_second: 1185,
-            length_pulse_zero: 1185,
-            length_pulse_one: 1185,
-            length_tone_pilot: 4096,
-            pause: 2000,
-            payload: DataPayload::default(),
+            length_pulse_pilot: timings.length_pulse_pilot,
+            length_pulse_sync_first: timings.length_pulse_sync_first,
+            length_pulse_sync_second: timings.length_pulse_sync_second,
+            length_pulse_zero: timings.length_pulse_zero,
+            length_pulse_one: timings.length_pulse_one,
+            length_tone_pilot: timings.length_tone_pilot,
+            pause,
+            payload,
+        }
+    }
+
+    /// Returns the pilot, sync and data pulse timings of the block.
+    pub fn timings(&self) -> TurboSpeedTimings {
+        TurboSpeedTimings {
+            length_pulse_pilot: self.length_pulse_pilot,
+            length_pulse_sync_first: self.length_pulse_sync_first,
+            length_pulse_sync_second: self.length_pulse_sync_second,
+            length_pulse_zero: self.length_pulse_zero,
+            length_pulse_one: self.length_pulse_one,
+            length_tone_pilot: self.length_tone_pilot,
         }
     }
 }
@@ -87,6 +145,8 @@ impl Block for TurboSpeedDataBlock {
     }
 
     fn get_waveforms(&self, config: Arc<Config>, start_pulse_high: bool) -> Vec<Box<dyn Waveform + Send>> {
+        let length_pilot = self.length_pulse_pilot as u64 * self.length_tone_pilot as u64;
+        let length_sync = self.length_pulse_sync_first as u64 + self.length_pulse_sync_second as u64;
         let pilot_source = PilotWaveform::new(
             config.clone(),
             self.length_pulse_pilot,
@@ -98,14 +158,14 @@ impl Block for TurboSpeedDataBlock {
             self.length_pulse_sync_first,
             self.length_pulse_sync_second,
             if self.length_tone_pilot % 2 == 0 { start_pulse_high } else { !start_pulse_high },
-        );
+        ).following(length_pilot);
         let data_source = DataWaveform::new(
             config.clone(),
             self.length_pulse_zero,
             self.length_pulse_one,
             self.payload.clone(),
             if self.length_tone_pilot % 2 == 0 { start_pulse_high } else { !start_pulse_high },
-        );
+        ).following(length_pilot + length_sync);
         let pause_source = PauseWaveform::new(config.clone(), self.pause, PauseType::Zero);
 
         return vec![Box::new(pilot_source), Box::new(sync_pulses_source), Box::new(data_source), Box::new(pause_source)];
@@ -119,6 +179,21 @@ impl Block for TurboSpeedDataBlock {
         return if self.length_tone_pilot % 2 == 0 { self_start_pulse_high } else { !self_start_pulse_high };
     }
 
+    fn pulses(&self, start_pulse_high: bool) -> PulseStream {
+        let pilot = pulses::tone(self.length_pulse_pilot, self.length_tone_pilot, start_pulse_high);
+        let sync_start_pulse_high = if self.length_tone_pilot.is_multiple_of(2) { start_pulse_high } else { !start_pulse_high };
+        let sync = pulses::sequence(vec![self.length_pulse_sync_first, self.length_pulse_sync_second], sync_start_pulse_high);
+        let data = pulses::from_pulses(DataPulseIterator::new(
+            pulses::pulse_config(),
+            self.length_pulse_zero,
+            self.length_pulse_one,
+            self.payload.clone(),
+            sync_start_pulse_high,
+        ));
+
+        return pulses::with_pause(pilot.chain(sync).chain(data), self.pause);
+    }
+
     fn clone_box(&self) -> Box<dyn Block> {
         Box::new(self.clone())
     }
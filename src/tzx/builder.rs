@@ -0,0 +1,212 @@
+//! Programmatic construction of TZX data.
+
+use crate::tzx::{
+    Header,
+    TzxData,
+    blocks::{
+        ArchiveInfo,
+        ArchiveInfoEntry,
+        ArchiveInfoEntryType,
+        Block,
+        CallSequence,
+        CustomInfoBlock,
+        DirectRecording,
+        GeneralizedDataBlock,
+        GroupEnd,
+        GroupStart,
+        HardwareTypeBlock,
+        HardwareTypeBlockEntry,
+        JumpToBlock,
+        KansasCityStandardDataBlock,
+        LoopEnd,
+        LoopStart,
+        MessageBlock,
+        PauseOrStopTapeCommand,
+        PulseSequence,
+        PureDataBlock,
+        PureTone,
+        ReturnFromSequence,
+        SelectBlock,
+        SelectBlockEntry,
+        SetSignalLevel,
+        StandardSpeedDataBlock,
+        StopTapeIf48K,
+        TextDescription,
+        TurboSpeedDataBlock,
+        TurboSpeedTimings,
+        generalized_data_block::{PilotRLE, SymbolDefinition},
+    },
+    data::DataPayload,
+};
+
+/// A fluent builder for [TzxData].
+///
+/// Each method appends a block to the data being built, with all length fields calculated from the supplied
+/// contents. Use [TzxData::builder()] to obtain a builder:
+///
+/// ```
+/// use rtzx::TzxData;
+/// use rtzx::tzx::blocks::{ArchiveInfoEntryType, TurboSpeedTimings};
+///
+/// let tzx_data = TzxData::builder()
+///     .archive_info(&[(ArchiveInfoEntryType::FullTitle, "Test Tape")])
+///     .standard_block(vec![0x00, 0x03, 0x00])
+///     .turbo_block(TurboSpeedTimings::default(), vec![0xff, 0x01, 0x02, 0xfc])
+///     .pause(2000)
+///     .build();
+/// assert_eq!(tzx_data.blocks.len(), 4);
+/// ```
+///
+/// Blocks that are not covered by a convenience method (or that need non-default settings, such as a custom pause
+/// after a data block) can be constructed directly and appended with [.block()](TzxDataBuilder::block). The
+/// deprecated emulation info, snapshot and instructions blocks are not supported.
+#[derive(Clone, Debug, Default)]
+pub struct TzxDataBuilder {
+    header: Header,
+    blocks: Vec<Box<dyn Block + 'static>>,
+}
+
+impl TzxDataBuilder {
+    pub fn new() -> Self { Self::default() }
+
+    /// Sets the TZX version recorded in the [Header]. Defaults to 1.20.
+    pub fn header(mut self, major: u8, minor: u8) -> Self {
+        self.header = Header::new(major, minor);
+        self
+    }
+
+    /// Appends an arbitrary block.
+    pub fn block(mut self, block: impl Block + 'static) -> Self {
+        self.blocks.push(Box::new(block));
+        self
+    }
+
+    /// Appends an already boxed block.
+    pub fn boxed_block(mut self, block: Box<dyn Block + 'static>) -> Self {
+        self.blocks.push(block);
+        self
+    }
+
+    /// Appends a [StandardSpeedDataBlock]. The payload should include the flag and checksum bytes.
+    ///
+    /// As per conversion of TAP files, the pause after the block is 1000ms for header blocks (flag byte below 128)
+    /// and 2000ms otherwise.
+    pub fn standard_block(self, payload: impl Into<DataPayload>) -> Self {
+        let payload = payload.into();
+        let pause = if payload.data.first().is_some_and(|flag| *flag < 128) { 1000 } else { 2000 };
+        self.block(StandardSpeedDataBlock::with_payload(pause, payload))
+    }
+
+    /// Appends a [TurboSpeedDataBlock] with the given timings and a 2000ms pause.
+    pub fn turbo_block(self, timings: TurboSpeedTimings, payload: impl Into<DataPayload>) -> Self {
+        self.block(TurboSpeedDataBlock::with_timings(timings, 2000, payload.into()))
+    }
+
+    /// Appends a [PureTone].
+    pub fn pure_tone(self, length_pulse: u16, length_tone: u16) -> Self {
+        self.block(PureTone::new(length_pulse, length_tone))
+    }
+
+    /// Appends the given pulse lengths as one or more [PulseSequence] blocks of up to 255 pulses each.
+    pub fn pulse_sequence(mut self, pulse_lengths: &[u16]) -> Self {
+        for chunk in pulse_lengths.chunks(u8::MAX as usize) {
+            self = self.block(PulseSequence::new(chunk));
+        }
+        self
+    }
+
+    /// Appends a [PureDataBlock] with a 2000ms pause.
+    pub fn pure_data_block(self, length_pulse_zero: u16, length_pulse_one: u16, payload: impl Into<DataPayload>) -> Self {
+        self.block(PureDataBlock::new(length_pulse_zero, length_pulse_one, 2000, payload.into()))
+    }
+
+    /// Appends a [DirectRecording] with a 2000ms pause.
+    pub fn direct_recording(self, length_sample: u16, payload: impl Into<DataPayload>) -> Self {
+        self.block(DirectRecording::new(length_sample, 2000, payload.into()))
+    }
+
+    /// Appends a [GeneralizedDataBlock] from symbol tables, a pilot run-length encoding and a sequence of data symbol
+    /// keys, which are packed into the data stream.
+    pub fn generalized_block(
+        self,
+        pause: u16,
+        symbols_pilot: Vec<SymbolDefinition>,
+        pilot_data: Vec<PilotRLE>,
+        symbols_data: Vec<SymbolDefinition>,
+        data_symbols: &[u8],
+    ) -> Self {
+        let data = GeneralizedDataBlock::pack_data_symbols(symbols_data.len(), data_symbols);
+        self.block(GeneralizedDataBlock::new(pause, symbols_pilot, pilot_data, symbols_data, data_symbols.len() as u32, data))
+    }
+
+    /// Appends a [KansasCityStandardDataBlock] using standard MSX timings and framing.
+    pub fn kansas_city_standard_block(self, payload: impl Into<DataPayload>) -> Self {
+        let mut block = KansasCityStandardDataBlock::new();
+        block.payload = payload.into();
+        self.block(block)
+    }
+
+    /// Appends a [PauseOrStopTapeCommand] of the given length in milliseconds.
+    pub fn pause(self, pause_ms: u16) -> Self {
+        self.block(PauseOrStopTapeCommand::new(pause_ms))
+    }
+
+    /// Appends a zero length [PauseOrStopTapeCommand], i.e. a 'stop tape' command.
+    pub fn stop_tape(self) -> Self { self.pause(0) }
+
+    /// Appends a [StopTapeIf48K] block.
+    pub fn stop_tape_if_48k(self) -> Self { self.block(StopTapeIf48K::new()) }
+
+    /// Appends a [SetSignalLevel] block.
+    pub fn signal_level(self, high: bool) -> Self { self.block(SetSignalLevel::new(high)) }
+
+    /// Appends a [GroupStart] with the given name.
+    pub fn group_start(self, name: &str) -> Self { self.block(GroupStart::new(name)) }
+
+    /// Appends a [GroupEnd].
+    pub fn group_end(self) -> Self { self.block(GroupEnd::new()) }
+
+    /// Appends a [JumpToBlock] with the given relative offset.
+    pub fn jump(self, offset: i16) -> Self { self.block(JumpToBlock::new(offset)) }
+
+    /// Appends a [LoopStart] with the given number of repetitions.
+    pub fn loop_start(self, repetitions: u16) -> Self { self.block(LoopStart::new(repetitions)) }
+
+    /// Appends a [LoopEnd].
+    pub fn loop_end(self) -> Self { self.block(LoopEnd::new()) }
+
+    /// Appends a [CallSequence] with the given relative block offsets.
+    pub fn call_sequence(self, block_offsets: &[i16]) -> Self { self.block(CallSequence::new(block_offsets)) }
+
+    /// Appends a [ReturnFromSequence].
+    pub fn return_from_sequence(self) -> Self { self.block(ReturnFromSequence::new()) }
+
+    /// Appends a [SelectBlock] with entries given as relative block offset and description pairs.
+    pub fn select(self, entries: &[(i16, &str)]) -> Self {
+        let entries = entries.iter().map(|(offset, text)| SelectBlockEntry::new(*offset, text)).collect();
+        self.block(SelectBlock::new(entries))
+    }
+
+    /// Appends a [TextDescription].
+    pub fn text_description(self, text: &str) -> Self { self.block(TextDescription::new(text)) }
+
+    /// Appends a [MessageBlock] to be displayed for the given number of seconds.
+    pub fn message(self, display_for_secs: u8, text: &str) -> Self { self.block(MessageBlock::new(display_for_secs, text)) }
+
+    /// Appends an [ArchiveInfo] block with the given entries.
+    pub fn archive_info(self, entries: &[(ArchiveInfoEntryType, &str)]) -> Self {
+        let entries = entries.iter().map(|(entry_type, text)| ArchiveInfoEntry::new(*entry_type, text)).collect();
+        self.block(ArchiveInfo::new(entries))
+    }
+
+    /// Appends a [HardwareTypeBlock] with the given entries.
+    pub fn hardware_type(self, entries: Vec<HardwareTypeBlockEntry>) -> Self { self.block(HardwareTypeBlock::new(entries)) }
+
+    /// Appends a [CustomInfoBlock].
+    pub fn custom_info(self, id: &str, data: Vec<u8>) -> Self { self.block(CustomInfoBlock::new(id, data)) }
+
+    /// Consumes the builder, returning the built [TzxData].
+    pub fn build(self) -> TzxData {
+        TzxData { header: self.header, blocks: self.blocks }
+    }
+}
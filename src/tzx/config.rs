@@ -2,9 +2,11 @@
 
 use bon::Builder;
 use rodio::SampleRate;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::Duration;
 
-use crate::tzx::Platform;
+use crate::tzx::{Platform, output::{OutputChannels, OutputShape}};
 
 /// Configuration information for rtzx.
 #[derive(Clone, Debug, Builder, Default)]
@@ -21,13 +23,99 @@ pub struct Config {
     /// Modifies the lengths of [Pulse](crate::tzx::waveforms::Pulse)s to increase or decrease playback duration.
     /// Positive integers increase pulse lengths by that percentage, negative integers reduce them.
     #[builder(default = 0)]
-    pub playback_duration_percent: i32
+    pub playback_duration_percent: i32,
+    /// Overrides of the playback duration adjustment for individual blocks, keyed by zero-based block index. Use
+    /// [.for_block()](Config::for_block) to obtain the configuration for a block.
+    #[builder(default)]
+    pub block_playback_duration_percent: BTreeMap<usize, i32>,
+    /// The [OutputShape] of pulses for conversion / playback. Defaults to square pulses.
+    #[builder(default)]
+    pub output_shape: OutputShape,
+    /// The rise time of edges in microseconds for [OutputShape::Trapezoid]. Defaults to 50.
+    #[builder(default = 50)]
+    pub rise_time_us: u32,
+    /// The output amplitude in dBFS for conversion / playback. Defaults to 0, i.e. full scale.
+    #[builder(default = 0.0)]
+    pub amplitude_db: f32,
+    /// Inverts the polarity of the output, for interfaces that invert the signal. Defaults to false.
+    #[builder(default = false)]
+    pub invert: bool,
+    /// The level of otherwise silent pauses ([PauseType::Zero](crate::tzx::waveforms::PauseType::Zero)) as a
+    /// proportion of full scale from -1.0 to 1.0, i.e. a DC offset while the tape is idle. Defaults to 0. The level is
+    /// part of the signal, so it is scaled by the [amplitude](Config::amplitude_db) and inverted with the
+    /// [polarity](Config::invert).
+    #[builder(default = 0.0)]
+    pub pause_level: f32,
+    /// The [OutputChannels] for conversion / playback. Defaults to mono.
+    #[builder(default)]
+    pub channels: OutputChannels,
+    /// Replaces any silence at the start of the tape with a pause of this length in milliseconds. See
+    /// [block_waveforms](crate::tzx::timing::block_waveforms).
+    pub initial_silence_ms: Option<u16>,
+    /// The minimum number of pilot pulses for standard and turbo speed data blocks. See
+    /// [adjust_timings](crate::tzx::timing::adjust_timings).
+    pub min_pilot_pulses: Option<u16>,
+    /// The minimum length in milliseconds of non-zero pauses. See
+    /// [adjust_timings](crate::tzx::timing::adjust_timings).
+    pub min_gap_ms: Option<u16>,
 }
 
 impl Config {
     /// Returns the desired size of the playback buffer in samples.
     pub fn buffer_size(&self) -> u32 { self.sample_rate * self.buffer_length_ms / 1000 }
 
+    /// Returns the number of samples per ZX Spectrum t cycle for the configured playback duration adjustment and
+    /// sample rate.
+    pub fn samples_per_t_cycle(&self) -> f64 {
+        return self.platform.t_cycle_secs_playback(self.playback_duration_percent) * self.sample_rate as f64
+    }
+
+    /// Returns the duration of the given number of ZX Spectrum t cycles starting `start` t cycles into a block, with
+    /// both ends rounded to a whole number of samples.
+    ///
+    /// Waveforms use this for their total duration, since pulses played with a
+    /// [SampleClock](crate::tzx::waveforms::pulse::SampleClock) add up to the exact t cycle total rather than the sum
+    /// of individually rounded pulse lengths.
+    pub fn t_cycles_duration(&self, start: u64, t_cycles: u64) -> Duration {
+        let samples = |t_cycles: u64| (t_cycles as f64 * self.samples_per_t_cycle()).round();
+        return Duration::from_secs_f64((samples(start + t_cycles) - samples(start)) / self.sample_rate as f64)
+    }
+
+    /// Returns the configuration for the block at the given index, with any override of the playback duration
+    /// adjustment for that block applied.
+    ///
+    /// Pass this to [Block::get_waveforms](crate::tzx::blocks::Block::get_waveforms) so that per-block overrides take
+    /// effect.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use rtzx::tzx::Config;
+    ///
+    /// let config = Arc::new(Config::builder()
+    ///     .playback_duration_percent(5)
+    ///     .block_playback_duration_percent([(3, 10)].into())
+    ///     .build());
+    /// assert_eq!(config.for_block(2).playback_duration_percent, 5);
+    /// assert_eq!(config.for_block(3).playback_duration_percent, 10);
+    /// ```
+    pub fn for_block(self: &Arc<Self>, index: usize) -> Arc<Config> {
+        return match self.block_playback_duration_percent.get(&index) {
+            Some(percent) if *percent != self.playback_duration_percent => {
+                Arc::new(Config { playback_duration_percent: *percent, ..(**self).clone() })
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns a copy of the configuration with the block indices of per-block overrides mapped to new indices, for
+    /// when blocks have been inserted, removed or split into pieces. Overrides of blocks mapped to `None` are dropped.
+    pub fn remap_blocks(&self, new_index: impl Fn(usize) -> Option<usize>) -> Config {
+        let block_playback_duration_percent = self.block_playback_duration_percent.iter()
+            .filter_map(|(index, percent)| new_index(*index).map(|index| (index, *percent)))
+            .collect();
+        return Config { block_playback_duration_percent, ..self.clone() };
+    }
+
     /// Returns the playback buffer delay as a [Duration].
     pub fn buffer_delay(&self) -> Duration {
         Duration::from_secs_f64(self.buffer_size() as f64 / self.sample_rate as f64)
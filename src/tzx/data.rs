@@ -3,6 +3,7 @@
 use binrw::{
     binrw,
 };
+use bitvec::prelude::*;
 
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -73,6 +74,25 @@ impl DataPayload {
         }
     }
 
+    /// Creates a new [DataPayload] from a sequence of bits, packed most significant bit first.
+    pub fn from_bits(bits: &[bool]) -> Self {
+        let data = bits.chunks(8)
+            .map(|chunk| chunk.iter().enumerate().fold(0u8, |byte, (index, bit)| byte | ((*bit as u8) << (7 - index))))
+            .collect();
+        let used_bits = match bits.len() % 8 {
+            0 if bits.is_empty() => 0,
+            0 => 8,
+            remainder => remainder as u8,
+        };
+        DataPayload::new(used_bits, Arc::new(data))
+    }
+
+    /// Returns the bits of the data, most significant bit first, excluding unused bits in the last byte.
+    pub fn bits(&self) -> Vec<bool> {
+        if self.data.is_empty() { return Vec::new() }
+        self.data.view_bits::<Msb0>()[..self.total_bits()].iter().map(|bit| *bit).collect()
+    }
+
     /// Returns the length of the data in bytes, including unused bits.
     pub fn len(&self) -> usize { self.data.len() }
 
@@ -132,6 +152,14 @@ impl Default for DataPayload {
     fn default() -> Self { DataPayload::new(0, Arc::new(Vec::new())) }
 }
 
+impl From<Vec<u8>> for DataPayload {
+    fn from(value: Vec<u8>) -> Self { DataPayload::new(8, Arc::new(value)) }
+}
+
+impl From<&[u8]> for DataPayload {
+    fn from(value: &[u8]) -> Self { DataPayload::new(8, Arc::new(value.to_vec())) }
+}
+
 impl fmt::Display for DataPayload {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "DataPayload: {} bytes (used_bits: {}; total_bits: {})", self.data.len(), self.used_bits, self.total_bits())
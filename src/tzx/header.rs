@@ -18,6 +18,9 @@ pub struct Header {
 
 impl Header {
     pub fn new(major: u8, minor: u8) -> Self { Header { major, minor }}
+
+    /// Returns the major and minor version.
+    pub fn version(&self) -> (u8, u8) { (self.major, self.minor) }
 }
 
 impl Default for Header {
@@ -0,0 +1,359 @@
+//! Optimisation of [DirectRecording] blocks into data blocks.
+//!
+//! Direct recordings made from raw tape captures often contain data that is really a standard or turbo encoding:
+//! a pilot tone, two sync pulses, and a sequence of pulse pairs of two lengths encoding zeros and ones. Such
+//! sections can be replaced with a [TurboSpeedDataBlock], or with a [PureDataBlock] where there is no pilot tone,
+//! which is far smaller and plays back with exact timings.
+
+use std::sync::Arc;
+
+use crate::tzx::{
+    Config,
+    blocks::{
+        Block,
+        DirectRecording,
+        PureDataBlock,
+        TurboSpeedDataBlock,
+        TurboSpeedTimings,
+    },
+    data::DataPayload,
+    waveforms::DirectWaveform,
+};
+
+/// The minimum number of pulses accepted as a pilot tone.
+const MIN_PILOT_PULSES: usize = 256;
+
+/// The minimum number of bits accepted as data for a [PureDataBlock], where there is no pilot tone to confirm
+/// that the section is data.
+const MIN_PURE_DATA_BITS: usize = 64;
+
+/// The minimum ratio between the one and zero pulse lengths for them to be distinguished.
+const MIN_ONE_ZERO_RATIO: f64 = 1.3;
+
+/// Attempts to replace sections of a [DirectRecording] with [TurboSpeedDataBlock]s and [PureDataBlock]s.
+///
+/// Pulses are accepted as matching a length where they are within `tolerance_percent` of it, or within one sample
+/// of the recording to allow for quantisation. Each replacement is verified by regenerating its pulses and comparing
+/// them pulse-for-pulse against the recording with the same tolerance. Sections that do not match are retained as
+/// [DirectRecording]s, with the original pause following the last block.
+///
+/// Returns `None` if no sections could be replaced.
+pub fn optimise_direct_recording(block: &DirectRecording, tolerance_percent: u8) -> Option<Vec<Box<dyn Block>>> {
+    if block.payload().data.is_empty() { return None }
+
+    let waveform = DirectWaveform::new(Arc::new(Config::default()), block.length_sample(), block.payload().clone());
+    let pulses: Vec<u32> = waveform.pulse_lengths().into_iter().map(|(length, _)| length).collect();
+    let matcher = PulseMatcher { tolerance_percent, length_sample: block.length_sample() as u32 };
+
+    // Sections of the recording as pulse index ranges, with the data block replacing each.
+    let mut replacements: Vec<(usize, usize, Box<dyn Block>)> = Vec::new();
+
+    let mut index = 0;
+    while index < pulses.len() {
+        if let Some((end, replacement)) = detect_turbo(&pulses, index, &matcher).or_else(|| detect_pure_data(&pulses, index, &matcher)) {
+            replacements.push((index, end, replacement));
+            index = end;
+        } else if let Some(pilot_end) = pilot_run_end(&pulses, index, &matcher) {
+            // Skip the remainder of an unusable pilot tone rather than rescanning it pulse by pulse.
+            index = pilot_end;
+        } else if let Some((data_end, ..)) = detect_data(&pulses, index, &matcher)
+            && data_end - index >= 2 * MIN_PURE_DATA_BITS {
+            // Likewise skip the remainder of data that failed verification. Shorter runs are rescanned, as they may
+            // be misaligned by a pulse with the start of the data.
+            index = data_end;
+        } else {
+            index += 1;
+        }
+    }
+
+    if replacements.is_empty() { return None }
+
+    let bits = block.payload().bits();
+    // The offset of the first bit of each pulse in the recording, and of the end of the recording.
+    let bit_offsets: Vec<usize> = std::iter::once(0)
+        .chain(pulses.iter().scan(0, |offset, length| {
+            *offset += (length / block.length_sample() as u32) as usize;
+            Some(*offset)
+        }))
+        .collect();
+    let bit_offset = |pulse_index: usize| -> usize { bit_offsets[pulse_index] };
+
+    let mut blocks: Vec<Box<dyn Block>> = Vec::new();
+    let mut pulse_index = 0;
+    for (start, end, replacement) in replacements {
+        if start > pulse_index {
+            let payload = DataPayload::from_bits(&bits[bit_offset(pulse_index)..bit_offset(start)]);
+            blocks.push(Box::new(DirectRecording::new(block.length_sample(), 0, payload)));
+        }
+        blocks.push(replacement);
+        pulse_index = end;
+    }
+
+    if pulse_index < pulses.len() {
+        let payload = DataPayload::from_bits(&bits[bit_offset(pulse_index)..]);
+        blocks.push(Box::new(DirectRecording::new(block.length_sample(), block.pause(), payload)));
+    } else {
+        set_pause(blocks.last_mut().unwrap(), block.pause());
+    }
+
+    return Some(blocks);
+}
+
+/// Matches pulse lengths within a tolerance.
+struct PulseMatcher {
+    tolerance_percent: u8,
+    length_sample: u32,
+}
+
+impl PulseMatcher {
+    fn matches(&self, length: u32, expected: f64) -> bool {
+        let tolerance = (expected * self.tolerance_percent as f64 / 100.0).max(self.length_sample as f64);
+        (length as f64 - expected).abs() <= tolerance
+    }
+
+    /// Returns true if the pulses match the expected lengths pulse-for-pulse.
+    fn verify(&self, pulses: &[u32], expected: &[u16]) -> bool {
+        pulses.len() == expected.len() && pulses.iter().zip(expected).all(|(length, expected)| self.matches(*length, *expected as f64))
+    }
+}
+
+/// Returns the end of a run of at least [MIN_PILOT_PULSES] similar length pulses starting at the given index.
+fn pilot_run_end(pulses: &[u32], start: usize, matcher: &PulseMatcher) -> Option<usize> {
+    let mut total = 0u64;
+    let mut end = start;
+    while end < pulses.len() && (end == start || matcher.matches(pulses[end], total as f64 / (end - start) as f64)) {
+        total += pulses[end] as u64;
+        end += 1;
+    }
+    return if end - start >= MIN_PILOT_PULSES { Some(end) } else { None };
+}
+
+/// Detects a pilot tone, two sync pulses and data starting at the given index, returning the end of the section and
+/// a verified [TurboSpeedDataBlock] to replace it.
+fn detect_turbo(pulses: &[u32], start: usize, matcher: &PulseMatcher) -> Option<(usize, Box<dyn Block>)> {
+    let pilot_end = pilot_run_end(pulses, start, matcher)?;
+    let length_tone_pilot = u16::try_from(pilot_end - start).ok()?;
+    let length_pulse_pilot = mean(&pulses[start..pilot_end])?;
+
+    let sync = pulses.get(pilot_end..pilot_end + 2)?;
+    let (data_end, length_pulse_zero, length_pulse_one, bits) = detect_data(pulses, pilot_end + 2, matcher)?;
+
+    let timings = TurboSpeedTimings {
+        length_pulse_pilot,
+        length_pulse_sync_first: u16::try_from(sync[0]).ok()?,
+        length_pulse_sync_second: u16::try_from(sync[1]).ok()?,
+        length_pulse_zero,
+        length_pulse_one,
+        length_tone_pilot,
+    };
+    let block = TurboSpeedDataBlock::with_timings(timings, 0, DataPayload::from_bits(&bits));
+
+    if !matcher.verify(&pulses[start..data_end], &turbo_pulses(&block)) { return None }
+
+    return Some((data_end, Box::new(block)));
+}
+
+/// Detects data with no pilot tone starting at the given index, returning the end of the section and a verified
+/// [PureDataBlock] to replace it.
+fn detect_pure_data(pulses: &[u32], start: usize, matcher: &PulseMatcher) -> Option<(usize, Box<dyn Block>)> {
+    let (data_end, length_pulse_zero, length_pulse_one, bits) = detect_data(pulses, start, matcher)?;
+    if bits.len() < MIN_PURE_DATA_BITS { return None }
+
+    let payload = DataPayload::from_bits(&bits);
+    let expected = data_pulses(length_pulse_zero, length_pulse_one, &payload);
+    if !matcher.verify(&pulses[start..data_end], &expected) { return None }
+
+    return Some((data_end, Box::new(PureDataBlock::new(length_pulse_zero, length_pulse_one, 0, payload))));
+}
+
+/// Detects a sequence of pulse pairs of two lengths starting at the given index, returning the end of the sequence,
+/// the zero and one pulse lengths, and the decoded bits.
+fn detect_data(pulses: &[u32], start: usize, matcher: &PulseMatcher) -> Option<(usize, u16, u16, Vec<bool>)> {
+    // Estimate the zero and one pulse lengths by splitting the first pulses around the midpoint of their range.
+    // Longer pulses, e.g. at the end of the data, are excluded from the estimate.
+    let sample = pulses.get(start..pulses.len().min(start + 256))?;
+    let min = *sample.iter().min()? as f64;
+    let sample: Vec<u32> = sample.iter().copied().filter(|length| (*length as f64) <= min * 3.0).collect();
+    let max = *sample.iter().max()? as f64;
+    let threshold = (min + max) / 2.0;
+    let zero = mean(&sample.iter().copied().filter(|length| (*length as f64) < threshold).collect::<Vec<_>>())? as f64;
+    let one = mean(&sample.iter().copied().filter(|length| (*length as f64) >= threshold).collect::<Vec<_>>())? as f64;
+    // The lengths are compared after estimation, as quantisation widens the range of the pulses themselves. Where a
+    // pulse can match both lengths, bits could be decoded wrongly yet still pass verification.
+    if one / zero < MIN_ONE_ZERO_RATIO || matcher.matches(one.round() as u32, zero) || matcher.matches(zero.round() as u32, one) {
+        return None;
+    }
+
+    let mut bits = Vec::new();
+    let mut end = start;
+    while let Some(pair) = pulses.get(end..end + 2) {
+        let bit = if pair.iter().all(|length| matcher.matches(*length, zero)) {
+            false
+        } else if pair.iter().all(|length| matcher.matches(*length, one)) {
+            true
+        } else {
+            break;
+        };
+        bits.push(bit);
+        end += 2;
+    }
+    if bits.is_empty() { return None }
+
+    // Refine the pulse lengths using all of the data.
+    let zeros: Vec<u32> = pulses[start..end].iter().copied().filter(|length| matcher.matches(*length, zero)).collect();
+    let ones: Vec<u32> = pulses[start..end].iter().copied().filter(|length| !matcher.matches(*length, zero)).collect();
+    let length_pulse_zero = mean(&zeros).unwrap_or(zero.round() as u16);
+    let length_pulse_one = mean(&ones).unwrap_or(one.round() as u16);
+
+    return Some((end, length_pulse_zero, length_pulse_one, bits));
+}
+
+/// Returns the pulse lengths generated by a [TurboSpeedDataBlock], excluding the pause.
+fn turbo_pulses(block: &TurboSpeedDataBlock) -> Vec<u16> {
+    let mut pulses = vec![block.length_pulse_pilot; block.length_tone_pilot as usize];
+    pulses.push(block.length_pulse_sync_first);
+    pulses.push(block.length_pulse_sync_second);
+    pulses.extend(data_pulses(block.length_pulse_zero, block.length_pulse_one, &block.payload));
+    return pulses;
+}
+
+/// Returns the pulse lengths generated for a data payload, two pulses per bit.
+fn data_pulses(length_pulse_zero: u16, length_pulse_one: u16, payload: &DataPayload) -> Vec<u16> {
+    payload.bits().iter()
+        .flat_map(|bit| {
+            let length = if *bit { length_pulse_one } else { length_pulse_zero };
+            [length, length]
+        })
+        .collect()
+}
+
+fn mean(pulses: &[u32]) -> Option<u16> {
+    if pulses.is_empty() { return None }
+    let mean = pulses.iter().map(|length| *length as u64).sum::<u64>() as f64 / pulses.len() as f64;
+    u16::try_from(mean.round() as u64).ok()
+}
+
+fn set_pause(block: &mut Box<dyn Block>, pause: u16) {
+    if let Some(block) = block.as_any_mut().downcast_mut::<TurboSpeedDataBlock>() {
+        block.pause = pause;
+    } else if let Some(block) = block.as_any_mut().downcast_mut::<PureDataBlock>() {
+        block.set_pause(pause);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tzx::TzxData;
+
+    /// The sample length of a recording at 44.1kHz, in ZX Spectrum t cycles.
+    const LENGTH_SAMPLE: u16 = 79;
+
+    const PAUSE: u16 = 1000;
+
+    /// Returns pseudo-random bytes, so that data contains runs of zeros and ones of varying lengths.
+    fn random_bytes(count: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        return (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 24) as u8
+            })
+            .collect();
+    }
+
+    /// Returns the pulses of the first block of the data, without its pause.
+    fn block_pulses(mut tzx_data: TzxData) -> Vec<(u32, bool)> {
+        let block = &mut tzx_data.blocks[0];
+        set_pause(block, 0);
+        return block.pulses(false).collect();
+    }
+
+    /// Samples pulses into a [DirectRecording], as a capture of them would.
+    fn record(pulses: &[(u32, bool)]) -> DirectRecording {
+        let mut bits = Vec::new();
+        let mut time = 0u64;
+        for (length, high) in pulses {
+            time += *length as u64;
+            while (bits.len() as u64 + 1) * LENGTH_SAMPLE as u64 <= time {
+                bits.push(*high);
+            }
+        }
+        return DirectRecording::new(LENGTH_SAMPLE, PAUSE, DataPayload::from_bits(&bits));
+    }
+
+    fn assert_close(actual: u16, expected: u16) {
+        assert!(actual.abs_diff(expected) <= LENGTH_SAMPLE, "{} is not within a sample of {}", actual, expected);
+    }
+
+    #[test]
+    fn turbo_block_round_trips() {
+        let timings = TurboSpeedTimings::spectrum_standard(false);
+        let payload = random_bytes(200, 1);
+        let recording = record(&block_pulses(TzxData::builder().turbo_block(timings, payload.clone()).build()));
+
+        let blocks = optimise_direct_recording(&recording, 15).expect("the recording should be optimised");
+        assert_eq!(blocks.len(), 1);
+
+        let block = blocks[0].as_any().downcast_ref::<TurboSpeedDataBlock>().expect("a turbo speed data block");
+        assert_eq!(block.payload.data.as_slice(), payload.as_slice());
+        assert_eq!(block.length_tone_pilot, timings.length_tone_pilot);
+        assert_close(block.length_pulse_pilot, timings.length_pulse_pilot);
+        assert_close(block.length_pulse_sync_first, timings.length_pulse_sync_first);
+        assert_close(block.length_pulse_sync_second, timings.length_pulse_sync_second);
+        assert_close(block.length_pulse_zero, timings.length_pulse_zero);
+        assert_close(block.length_pulse_one, timings.length_pulse_one);
+        assert_eq!(block.pause, PAUSE);
+    }
+
+    #[test]
+    fn pure_data_block_round_trips() {
+        let pulses = block_pulses(TzxData::builder().pure_data_block(855, 1710, random_bytes(64, 2)).build());
+        let recording = record(&pulses);
+
+        let blocks = optimise_direct_recording(&recording, 15).expect("the recording should be optimised");
+        assert_eq!(blocks.len(), 1);
+
+        let block = blocks[0].as_any().downcast_ref::<PureDataBlock>().expect("a pure data block");
+        assert_eq!(block.pause(), PAUSE);
+
+        let mut optimised_pulses: Vec<(u32, bool)> = block.pulses(false).collect();
+        optimised_pulses.pop();
+        assert_eq!(optimised_pulses.len(), pulses.len());
+        for ((length, high), (expected_length, expected_high)) in optimised_pulses.iter().zip(&pulses) {
+            assert_eq!(high, expected_high);
+            assert_close(*length as u16, *expected_length as u16);
+        }
+    }
+
+    #[test]
+    fn noise_is_not_optimised() {
+        let recording = DirectRecording::new(LENGTH_SAMPLE, PAUSE, DataPayload::from_bits(
+            &random_bytes(4096, 3).iter().flat_map(|byte| (0..8).map(move |bit| byte & (1 << bit) != 0)).collect::<Vec<_>>(),
+        ));
+
+        assert!(optimise_direct_recording(&recording, 15).is_none());
+    }
+
+    #[test]
+    fn indistinct_zero_and_one_pulses_are_not_optimised() {
+        let timings = TurboSpeedTimings { length_pulse_zero: 1000, length_pulse_one: 1200, ..TurboSpeedTimings::spectrum_standard(false) };
+        let recording = record(&block_pulses(TzxData::builder().turbo_block(timings, random_bytes(200, 4)).build()));
+
+        assert!(optimise_direct_recording(&recording, 15).is_none());
+    }
+
+    #[test]
+    fn mismatched_pulse_is_retained() {
+        let timings = TurboSpeedTimings::spectrum_standard(false);
+        let mut pulses = block_pulses(TzxData::builder().turbo_block(timings, random_bytes(200, 5)).build());
+        let glitch = pulses.len() - 1000;
+        pulses[glitch].0 = pulses[glitch].0 * 3 / 2;
+        let recording = record(&pulses);
+
+        let blocks = optimise_direct_recording(&recording, 15).expect("the start of the recording should be optimised");
+        let block = blocks[0].as_any().downcast_ref::<TurboSpeedDataBlock>().expect("a turbo speed data block");
+        assert!(block.payload.total_bits() < 200 * 8);
+        assert!(blocks[1..].iter().any(|block| block.as_any().is::<DirectRecording>()));
+    }
+}
@@ -0,0 +1,438 @@
+//! Output shaping and levels.
+//!
+//! Waveforms produce ideal square pulses, switching between levels within a single sample. Some audio hardware rings
+//! badly on such edges, and loaders with weak edge detectors, e.g. on the Amstrad CPC, can load more reliably from a
+//! signal with shaped edges. A [Shaper] applies the [OutputShape] selected in the [Config] to a stream of samples,
+//! [OutputLevels] then sets the amplitude and polarity, and [OutputChannels] finally spreads the signal across mono
+//! or stereo channels. All are used for both conversion and playback.
+
+use clap::ValueEnum;
+use rodio::{
+    ChannelCount,
+    SampleRate,
+    Source,
+    source::SeekError,
+};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+use strum_macros::Display;
+
+use crate::tzx::Config;
+
+/// The number of samples either side of the centre of the band-limiting filter used by [OutputShape::Blep].
+const BLEP_HALF_TAPS: usize = 16;
+
+/// The cutoff frequency of the band-limiting filter used by [OutputShape::Blep], as a proportion of the Nyquist
+/// frequency.
+const BLEP_CUTOFF: f32 = 0.9;
+
+/// The length in ms of the longest pulse shaped as a whole half sine wave by [OutputShape::Sine]. Longer pulses, e.g.
+/// pauses, are held at their level between sine shaped edges each half this length.
+const SINE_MAX_PULSE_MS: f32 = 5.0;
+
+/// The lowest amplitude in dBFS accepted by [OutputLevels::set_amplitude_db].
+pub const MIN_AMPLITUDE_DB: f32 = -60.0;
+
+/// The shape of output pulses.
+#[derive(Clone, Copy, Debug, Default, Display, Eq, PartialEq, Hash, ValueEnum)]
+pub enum OutputShape {
+    /// Square pulses, switching between levels within a single sample.
+    #[default]
+    Square,
+    /// Band-limited square pulses, with a band-limited step (BLEP) in place of each edge so that no frequencies above
+    /// the Nyquist frequency are produced.
+    Blep,
+    /// Trapezoid pulses, with linear edges taking the configured [Config::rise_time_us].
+    Trapezoid,
+    /// Sine-like pulses, with each pulse shaped as half a sine wave.
+    Sine,
+}
+
+/// The channels that output is spread across.
+#[derive(Clone, Copy, Debug, Default, Display, Eq, PartialEq, Hash, ValueEnum)]
+pub enum OutputChannels {
+    /// A single channel.
+    #[default]
+    Mono,
+    /// Stereo, with the signal on the left channel and silence on the right.
+    Left,
+    /// Stereo, with the signal on the right channel and silence on the left.
+    Right,
+    /// Stereo, with the signal on both channels.
+    Both,
+    /// Stereo, with the signal on the left channel and the inverted signal on the right. This roughly doubles the
+    /// swing into a tape input connected across the two channels.
+    Differential,
+}
+
+impl OutputChannels {
+    /// Returns the number of channels.
+    pub fn count(&self) -> ChannelCount {
+        return match self {
+            OutputChannels::Mono => 1,
+            _ => 2,
+        };
+    }
+
+    /// Returns a stream spreading the given mono samples across these channels, interleaved.
+    ///
+    /// ```
+    /// use rtzx::tzx::output::OutputChannels;
+    ///
+    /// let samples: Vec<f32> = OutputChannels::Differential.apply([1.0f32, -0.5].into_iter()).collect();
+    /// assert_eq!(samples, [1.0, -1.0, -0.5, 0.5]);
+    /// ```
+    pub fn apply<I: Iterator<Item = f32>>(&self, inner: I) -> Channels<I> {
+        return Channels { inner, channels: *self, pending: None }
+    }
+}
+
+/// Spreads a stream of mono samples across [OutputChannels], as returned by [OutputChannels::apply].
+#[derive(Clone, Debug)]
+pub struct Channels<I> {
+    inner: I,
+    channels: OutputChannels,
+    /// The sample for the right channel, once the left has been returned.
+    pending: Option<f32>,
+}
+
+impl<I: Iterator<Item = f32>> Iterator for Channels<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(sample) = self.pending.take() {
+            return Some(sample);
+        }
+
+        let sample = self.inner.next()?;
+        let (left, right) = match self.channels {
+            OutputChannels::Mono => return Some(sample),
+            OutputChannels::Left => (sample, 0.0),
+            OutputChannels::Right => (0.0, sample),
+            OutputChannels::Both => (sample, sample),
+            OutputChannels::Differential => (sample, -sample),
+        };
+        self.pending = Some(right);
+        return Some(left);
+    }
+}
+
+impl<I: Source> Source for Channels<I> {
+    fn channels(&self) -> ChannelCount { self.channels.count() }
+    fn sample_rate(&self) -> SampleRate { self.inner.sample_rate() }
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.pending = None;
+        return Ok(());
+    }
+}
+
+/// Applies the [OutputShape] selected in a [Config] to a stream of samples, without changing the number of samples.
+///
+/// The signal is assumed to change level at the start of the stream, as it does at the boundaries between the
+/// waveforms of a block, so that the first edge is shaped too.
+///
+/// ```
+/// use rtzx::Config;
+/// use rtzx::tzx::output::{OutputShape, Shaper};
+///
+/// // At 48kHz a 100µs rise time spans 4.8 samples.
+/// let config = Config::builder().sample_rate(48000).output_shape(OutputShape::Trapezoid).rise_time_us(100).build();
+/// let samples = [1.0f32, 1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0];
+/// let shaped: Vec<f32> = Shaper::new(samples.into_iter(), &config).collect();
+/// assert_eq!(shaped.len(), samples.len());
+/// assert!(shaped[0] < shaped[1] && shaped[1] < shaped[2]);
+/// assert_eq!(shaped[4], 1.0);
+/// assert!(shaped[5] > -1.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Shaper<I> {
+    inner: I,
+    shape: OutputShape,
+    /// Input samples, from the earliest still needed by the band-limiting filter to those read ahead.
+    buffer: VecDeque<f32>,
+    /// The index in the buffer of the next sample to output.
+    position: usize,
+    exhausted: bool,
+    /// The band-limiting filter kernel for [OutputShape::Blep].
+    kernel: Vec<f32>,
+    /// The maximum change in level per sample for [OutputShape::Trapezoid].
+    slew: f32,
+    /// The last output level for [OutputShape::Trapezoid].
+    level: Option<f32>,
+    /// The length in samples of the edges of long pulses for [OutputShape::Sine].
+    sine_edge: usize,
+    /// The index of the next sample within the current pulse for [OutputShape::Sine].
+    pulse_index: usize,
+    /// The length of the current pulse for [OutputShape::Sine], once its end has been read.
+    pulse_length: Option<usize>,
+}
+
+impl<I: Iterator<Item = f32>> Shaper<I> {
+    /// Shaper constructor.
+    pub fn new(inner: I, config: &Config) -> Self {
+        let rise_samples = (config.rise_time_us as f32 * config.sample_rate as f32 / 1_000_000.0).max(1.0);
+        let sine_edge = ((SINE_MAX_PULSE_MS * config.sample_rate as f32 / 2000.0).round() as usize).max(1);
+
+        return Self {
+            inner,
+            shape: config.output_shape,
+            buffer: VecDeque::new(),
+            position: 0,
+            exhausted: false,
+            kernel: if config.output_shape == OutputShape::Blep { blep_kernel() } else { Vec::new() },
+            slew: 2.0 / rise_samples,
+            level: None,
+            sine_edge,
+            pulse_index: 0,
+            pulse_length: None,
+        }
+    }
+
+    /// Reads ahead until the buffer holds the given number of samples after the next sample to output, or the inner
+    /// stream is exhausted.
+    fn fill(&mut self, lookahead: usize) {
+        while !self.exhausted && self.buffer.len() <= self.position + lookahead {
+            match self.inner.next() {
+                Some(sample) => {
+                    // History before the first sample is at the opposite level, giving an edge at the start.
+                    if self.buffer.is_empty() && self.shape == OutputShape::Blep {
+                        self.buffer.extend(std::iter::repeat_n(-sample, BLEP_HALF_TAPS));
+                        self.position = BLEP_HALF_TAPS;
+                    }
+                    self.buffer.push_back(sample);
+                }
+                None => self.exhausted = true,
+            }
+        }
+    }
+
+    /// Returns the buffered sample at the given offset from the next sample to output, holding the last sample beyond
+    /// the end of the stream.
+    fn sample_at(&self, offset: usize) -> f32 {
+        return *self.buffer.get(self.position + offset).or(self.buffer.back()).unwrap_or(&0.0);
+    }
+
+    fn next_blep(&mut self) -> f32 {
+        self.fill(BLEP_HALF_TAPS);
+        let start = self.position - BLEP_HALF_TAPS;
+        return self.kernel.iter().enumerate()
+            .map(|(index, coefficient)| {
+                let sample = self.buffer.get(start + index).or(self.buffer.back()).unwrap_or(&0.0);
+                coefficient * sample
+            })
+            .sum();
+    }
+
+    fn next_trapezoid(&mut self) -> f32 {
+        let sample = self.sample_at(0);
+        let level = self.level.unwrap_or(-sample);
+        let level = level + (sample - level).clamp(-self.slew, self.slew);
+        self.level = Some(level);
+        return level;
+    }
+
+    fn next_sine(&mut self) -> f32 {
+        let edge = self.sine_edge;
+        let level = self.sample_at(0);
+
+        if self.pulse_index == 0 {
+            // Look far enough ahead to find the end of a pulse short enough to be a whole half sine wave.
+            self.fill(edge * 2);
+            self.pulse_length = (1..=edge * 2).find(|offset| self.buffer.get(self.position + offset) != Some(&level));
+        } else if self.pulse_length.is_none() {
+            // Within a long pulse, the end is found as it comes into view one edge length ahead.
+            self.fill(edge);
+            if self.buffer.get(self.position + edge) != Some(&level) {
+                self.pulse_length = Some(self.pulse_index + edge);
+            }
+        }
+
+        let index = self.pulse_index;
+        let sample = match self.pulse_length {
+            Some(length) if length <= edge * 2 => level * (PI * (index as f32 + 0.5) / length as f32).sin(),
+            _ => {
+                let from_edge = index.min(self.pulse_length.map_or(usize::MAX, |length| length - index - 1));
+                if from_edge < edge { level * (PI * (from_edge as f32 + 0.5) / (edge * 2) as f32).sin() } else { level }
+            }
+        };
+
+        self.pulse_index += 1;
+        if self.pulse_length == Some(self.pulse_index) {
+            self.pulse_index = 0;
+        }
+        return sample;
+    }
+
+    /// Clears all state, e.g. after the inner stream has been seeked.
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.position = 0;
+        self.exhausted = false;
+        self.level = None;
+        self.pulse_index = 0;
+        self.pulse_length = None;
+    }
+}
+
+impl<I: Iterator<Item = f32>> Iterator for Shaper<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.shape == OutputShape::Square {
+            return self.inner.next();
+        }
+
+        self.fill(0);
+        if self.position >= self.buffer.len() {
+            return None;
+        }
+
+        let sample = match self.shape {
+            OutputShape::Square => self.sample_at(0),
+            OutputShape::Blep => self.next_blep(),
+            OutputShape::Trapezoid => self.next_trapezoid(),
+            OutputShape::Sine => self.next_sine(),
+        };
+
+        // Keep only the history needed by the band-limiting filter.
+        self.position += 1;
+        let history = if self.shape == OutputShape::Blep { BLEP_HALF_TAPS } else { 0 };
+        while self.position > history {
+            self.buffer.pop_front();
+            self.position -= 1;
+        }
+        return Some(sample);
+    }
+}
+
+impl<I: Source> Source for Shaper<I> {
+    fn channels(&self) -> ChannelCount { self.inner.channels() }
+    fn sample_rate(&self) -> SampleRate { self.inner.sample_rate() }
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.reset();
+        return Ok(());
+    }
+}
+
+/// Returns a windowed sinc low pass filter kernel with unity gain, cutting off at [BLEP_CUTOFF].
+fn blep_kernel() -> Vec<f32> {
+    let taps = BLEP_HALF_TAPS * 2 + 1;
+    let kernel: Vec<f32> = (0..taps)
+        .map(|index| {
+            let x = index as f32 - BLEP_HALF_TAPS as f32;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * BLEP_CUTOFF * x).sin() / (PI * BLEP_CUTOFF * x) };
+            // Blackman window.
+            let phase = 2.0 * PI * index as f32 / (taps - 1) as f32;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            sinc * window
+        })
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    return kernel.into_iter().map(|coefficient| coefficient / sum).collect();
+}
+
+/// The amplitude and polarity of output samples.
+///
+/// Clones share the same settings, so that they can be changed while samples are being output, e.g. from the play UI
+/// while [Levels] streams created from a clone are queued for playback.
+///
+/// ```
+/// use rtzx::Config;
+/// use rtzx::tzx::output::OutputLevels;
+///
+/// let levels = OutputLevels::new(&Config::builder().amplitude_db(-6.0).build());
+/// let mut samples = levels.apply([1.0f32, -1.0].into_iter());
+/// assert!((samples.next().unwrap() - 0.501).abs() < 0.001);
+///
+/// levels.set_invert(true);
+/// assert!((samples.next().unwrap() - 0.501).abs() < 0.001);
+/// ```
+#[derive(Clone, Debug)]
+pub struct OutputLevels {
+    /// The amplitude in dBFS, stored as the bits of an f32.
+    amplitude_db: Arc<AtomicU32>,
+    invert: Arc<AtomicBool>,
+    /// The factor to multiply samples by, stored as the bits of an f32. This is updated whenever the amplitude or
+    /// polarity is set, rather than computed for every sample.
+    gain: Arc<AtomicU32>,
+}
+
+impl OutputLevels {
+    /// Creates output levels with the amplitude and polarity of the given [Config].
+    pub fn new(config: &Config) -> Self {
+        let levels = Self {
+            amplitude_db: Arc::new(AtomicU32::new(0)),
+            invert: Arc::new(AtomicBool::new(config.invert)),
+            gain: Arc::new(AtomicU32::new(0)),
+        };
+        levels.set_amplitude_db(config.amplitude_db);
+        return levels;
+    }
+
+    /// Returns the amplitude in dBFS.
+    pub fn amplitude_db(&self) -> f32 { f32::from_bits(self.amplitude_db.load(Ordering::Relaxed)) }
+
+    /// Sets the amplitude in dBFS, limited to the range from [MIN_AMPLITUDE_DB] to full scale.
+    pub fn set_amplitude_db(&self, amplitude_db: f32) {
+        let amplitude_db = amplitude_db.clamp(MIN_AMPLITUDE_DB, 0.0);
+        self.amplitude_db.store(amplitude_db.to_bits(), Ordering::Relaxed);
+        self.update_gain();
+    }
+
+    /// Returns whether the polarity is inverted.
+    pub fn invert(&self) -> bool { self.invert.load(Ordering::Relaxed) }
+
+    /// Sets whether the polarity is inverted.
+    pub fn set_invert(&self, invert: bool) {
+        self.invert.store(invert, Ordering::Relaxed);
+        self.update_gain();
+    }
+
+    /// Returns the factor to multiply samples by, negative where the polarity is inverted.
+    pub fn gain(&self) -> f32 { f32::from_bits(self.gain.load(Ordering::Relaxed)) }
+
+    fn update_gain(&self) {
+        let gain = 10f32.powf(self.amplitude_db() / 20.0);
+        let gain = if self.invert() { -gain } else { gain };
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns a stream applying these levels to the given samples.
+    pub fn apply<I: Iterator<Item = f32>>(&self, inner: I) -> Levels<I> {
+        return Levels { inner, levels: self.clone() }
+    }
+}
+
+/// Applies [OutputLevels] to a stream of samples, as returned by [OutputLevels::apply].
+#[derive(Clone, Debug)]
+pub struct Levels<I> {
+    inner: I,
+    levels: OutputLevels,
+}
+
+impl<I: Iterator<Item = f32>> Iterator for Levels<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.inner.next().map(|sample| sample * self.levels.gain());
+    }
+}
+
+impl<I: Source> Source for Levels<I> {
+    fn channels(&self) -> ChannelCount { self.inner.channels() }
+    fn sample_rate(&self) -> SampleRate { self.inner.sample_rate() }
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn total_duration(&self) -> Option<Duration> { self.inner.total_duration() }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> { self.inner.try_seek(pos) }
+}
@@ -0,0 +1,129 @@
+//! Parse diagnostics and errors.
+
+use std::fmt;
+use std::io;
+
+use crate::tzx::{
+    RecoveryEnum,
+    blocks::BlockType,
+};
+
+/// Determines how parse errors in individual blocks are handled.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ParseMode {
+    /// Blocks that fail to parse are skipped and recorded in the [ParseReport]. Parsing continues with the next
+    /// block type identification byte.
+    #[default]
+    Lenient,
+    /// Parsing fails with [ParseError::Block] on the first block that fails to parse.
+    Strict,
+    /// Blocks that fail to parse are recorded in the [ParseReport], and the parser scans forward for the next offset
+    /// at which a block parses successfully and is followed by either another block or the end of the data. The
+    /// bytes skipped over are retained as a
+    /// [DamagedRegion](crate::tzx::blocks::DamagedRegion) block.
+    ///
+    /// For TAP data, which has no block type identification byte to resynchronise on, this behaves as
+    /// [ParseMode::Lenient].
+    Recover,
+}
+
+/// A problem encountered while parsing a block.
+#[derive(Debug)]
+pub struct ParseDiagnostic {
+    /// The index the block would have had in the parsed block list.
+    pub block_index: usize,
+    /// The offset of the start of the block in the data, including any block type identification byte.
+    pub offset: u64,
+    /// The block type, where known. This is always `None` for TAP data.
+    pub block_type: Option<RecoveryEnum<BlockType, u8>>,
+    /// The underlying parse error.
+    pub error: binrw::Error,
+    /// Whether the bytes of the block were retained as a [DamagedRegion](crate::tzx::blocks::DamagedRegion) in
+    /// [ParseMode::Recover], rather than skipped.
+    pub recovered: bool,
+}
+
+impl ParseDiagnostic {
+    /// Returns a description of what was done with the block, for prefixing to a warning.
+    pub fn outcome(&self) -> &'static str {
+        return if self.recovered { "recovered damaged region from" } else { "skipped" };
+    }
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block {} at offset {:#x}", self.block_index, self.offset)?;
+        match self.block_type {
+            Some(RecoveryEnum::Known(block_type)) => write!(f, " ({})", block_type)?,
+            Some(RecoveryEnum::Unknown(block_type_id)) => write!(f, " (undefined block type {:#04x})", block_type_id)?,
+            None => (),
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+/// A report of all problems encountered while parsing, returned alongside the parsed data.
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    pub diagnostics: Vec<ParseDiagnostic>,
+}
+
+impl ParseReport {
+    pub fn new() -> Self { Self::default() }
+
+    /// Returns true if no problems were encountered.
+    pub fn is_clean(&self) -> bool { self.diagnostics.is_empty() }
+
+    /// Returns true if any blocks that failed to parse were skipped, rather than retained as damaged regions, so
+    /// that writing the data would lose them.
+    pub fn has_skipped(&self) -> bool { self.diagnostics.iter().any(|diagnostic| !diagnostic.recovered) }
+
+    /// Records a diagnostic.
+    pub fn push(&mut self, diagnostic: ParseDiagnostic) { self.diagnostics.push(diagnostic) }
+}
+
+/// An error that causes parsing to fail outright.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The underlying reader failed with an error other than reaching the end of the data.
+    Io(io::Error),
+    /// The data could not be parsed at all, e.g. because the header is missing.
+    Parse(binrw::Error),
+    /// A block failed to parse in [ParseMode::Strict].
+    Block(ParseDiagnostic),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(why) => write!(f, "IO error: {}", why),
+            ParseError::Parse(why) => write!(f, "{}", why),
+            ParseError::Block(diagnostic) => write!(f, "Failed to parse {}", diagnostic),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self { ParseError::Io(e) }
+}
+
+impl From<binrw::Error> for ParseError {
+    fn from(e: binrw::Error) -> Self {
+        match e {
+            binrw::Error::Io(why) => ParseError::Io(why),
+            other => ParseError::Parse(other),
+        }
+    }
+}
+
+impl From<ParseError> for binrw::Error {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Io(why) => binrw::Error::Io(why),
+            ParseError::Parse(why) => why,
+            ParseError::Block(diagnostic) => diagnostic.error,
+        }
+    }
+}
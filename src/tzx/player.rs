@@ -1,24 +1,56 @@
 //! TZX player.
 
-use rodio::{Sink, Source};
-use std::sync::Arc;
+use rodio::{ChannelCount, SampleRate, Sink, Source, source::SeekError};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use crate::tzx::{
     Config,
-    blocks::Block,
+    blocks::{Block, GroupEnd, GroupStart},
+    output::{OutputLevels, Shaper},
+    timing::block_waveforms,
     TzxData,
     waveforms::Waveform,
 };
 
+/// The lowest playback duration adjustment that [Player::change_playback_duration_percent] will set.
+const MIN_PLAYBACK_DURATION_PERCENT: i32 = -90;
+
+/// A group of blocks, from a [GroupStart] block to its matching [GroupEnd] block.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BlockGroup {
+    /// The name of the group.
+    pub name: String,
+    /// The index of the [GroupStart] block.
+    pub start: usize,
+    /// The index of the matching [GroupEnd] block, or of the last block if the group is not ended.
+    pub end: usize,
+    /// The number of groups that this group is nested within.
+    pub depth: usize,
+}
+
+impl BlockGroup {
+    /// Returns whether the block with the given index is within the group, including its start and end blocks.
+    pub fn contains(&self, block_index: usize) -> bool { (self.start..=self.end).contains(&block_index) }
+}
+
 /// Provides a player for playing back [TzxData] to a [Sink].
 pub struct Player<'a> {
     config: Arc<Config>,
+    /// The output levels, which can be changed during playback.
+    pub levels: OutputLevels,
     sink: Sink,
+    /// The waveforms queued for the [WaveformQueue] being played by the sink.
+    queue: Arc<Mutex<QueuedWaveforms>>,
     tzx_data: &'a TzxData,
     pub blocks: Vec<Box<dyn Block>>,
+    /// The groups of blocks, in order of their start blocks.
+    pub groups: Vec<BlockGroup>,
     pub block_durations: Vec<std::time::Duration>,
+    /// The index of the first waveform of each block.
+    block_waveform_indices: Vec<usize>,
     pub waveforms: Vec<Box<dyn Waveform + Send>>,
     pub waveforms_original: Vec<Box<dyn Waveform + Send>>,
     pub waveform_durations: Vec<std::time::Duration>,
@@ -35,13 +67,16 @@ pub struct Player<'a> {
 impl<'a> Player<'a> {
     pub fn new(sink: Sink, config: Arc<Config>, tzx_data: &'a TzxData) -> Player<'a> {
         sink.pause();
-
         let mut player = Player {
+            levels: OutputLevels::new(&config),
             config,
             sink,
+            queue: Arc::new(Mutex::new(QueuedWaveforms::default())),
             tzx_data,
             blocks: vec![],
+            groups: vec![],
             block_durations: vec![],
+            block_waveform_indices: vec![],
             waveforms: vec![],
             waveforms_original: vec![],
             waveform_durations: vec![],
@@ -55,24 +90,26 @@ impl<'a> Player<'a> {
             is_seeking: false,
         };
 
-        player.read_blocks(true);
+        player.read_blocks(0);
+        player.groups = block_groups(&player.blocks);
+        player.requeue();
 
         return player;
     }
 
-    fn read_blocks(&mut self, start_pulse_high: bool) {
-        let mut current_start_pulse_high = start_pulse_high;
-        let blocks = self.tzx_data.blocks.clone();
-        for block in blocks {
-            self.append_block(&block, current_start_pulse_high);
-            current_start_pulse_high = block.next_block_start_pulse_high(self.config.clone(), current_start_pulse_high);
+    /// Reads the blocks from the given index onwards, which must follow on from those already read.
+    fn read_blocks(&mut self, first_block: usize) {
+        let blocks = &self.tzx_data.blocks;
+        let block_waveforms = block_waveforms(blocks, &self.config, first_block);
+        for (block, waveforms) in blocks[first_block..].iter().zip(block_waveforms) {
+            self.append_block(block.as_ref(), waveforms);
         }
     }
 
-    fn append_block(&mut self, block: &Box<dyn Block>, start_pulse_high: bool) {
+    fn append_block(&mut self, block: &dyn Block, waveforms: Vec<Box<dyn Waveform + Send>>) {
         let mut block_duration = Duration::ZERO;
-        let waveforms = block.get_waveforms(self.config.clone(), start_pulse_high);
 
+        self.block_waveform_indices.push(self.waveforms.len());
         for waveform in waveforms {
             let waveform_duration = match waveform.total_duration() {
                 Some(duration) => duration,
@@ -82,18 +119,23 @@ impl<'a> Player<'a> {
             self.total_duration += waveform_duration;
             self.waveforms.push(waveform.clone());
             self.waveform_durations.push(waveform_duration);
-            if self.waveform_queued_index < self.current_waveform_index + 1000 {
-                self.waveform_queued_index += 1;
-                let source: Box<dyn Source + Send> = waveform.clone();
-                self.sink.append(source);
-            }
             self.waveforms_original.push(waveform);
         }
 
-        self.blocks.push(block.clone());
+        self.blocks.push(block.clone_box());
         self.block_durations.push(block_duration);
     }
 
+    /// Queues waveforms for the sink until there are 1000 queued ahead of the current waveform, or all have been
+    /// queued.
+    fn queue_ahead(&mut self, queued: &mut QueuedWaveforms) {
+        let end = self.waveforms.len().min(self.current_waveform_index + 1000);
+        for index in self.waveform_queued_index..end {
+            queued.waveforms.push_back(self.waveforms_original[index].clone());
+        }
+        self.waveform_queued_index = self.waveform_queued_index.max(end);
+    }
+
     pub fn elapsed(&self) -> Duration {
         if let Some(start) = self.start_time {
             if self.is_paused {
@@ -118,7 +160,7 @@ impl<'a> Player<'a> {
             remaining -= block_duration;
         }
 
-        (remaining, self.block_durations[index])
+        (remaining, self.block_durations.get(index).copied().unwrap_or_default())
     }
 
     pub fn progress_in_current_waveform(&self) -> (Duration, Duration) {
@@ -133,17 +175,14 @@ impl<'a> Player<'a> {
             remaining -= waveform_duration;
         }
 
-        (remaining, self.waveform_durations[index])
+        (remaining, self.waveform_durations.get(index).copied().unwrap_or_default())
     }
 
     pub fn tick(&mut self) -> () {
         if !self.is_paused && !self.is_finished() {
             // Check to see if we need to queue any more data
-            while self.waveform_queued_index < self.waveforms.len() - 1 && (self.waveform_queued_index - self.current_waveform_index < 1000) {
-                self.waveform_queued_index += 1;
-                let source: Box<dyn Source + Send> = self.waveforms_original[self.waveform_queued_index].clone();
-                self.sink.append(source);
-            }
+            let queue = self.queue.clone();
+            self.queue_ahead(&mut queue.lock().unwrap());
             self.update_current_indices();
         }
     }
@@ -202,24 +241,13 @@ impl<'a> Player<'a> {
     }
 
     pub fn seek_block(&mut self, block_index: usize) {
-        // Do not attempt to seek while already seeking.
-        if self.is_seeking { return; }
-        self.is_seeking = true;
-
-        // Pause playback before seeking. We don't auto resume afterwards, the user is expected
-        // to manually unpause once they have seeked to the desired block.
-        // We also stop and clear the sink ready for rebuild.
-        self.is_paused = true;
-        self.sink.stop();
-        self.sink.clear();
-
         // Ensure seeked block index is bounded above (usize datatype ensures lower bound).
         let mut bounded_block_index = block_index;
         if bounded_block_index > self.blocks.len() {
-            bounded_block_index = self.blocks.len() - 1;
+            bounded_block_index = self.blocks.len().saturating_sub(1);
         }
 
-        // Recalculate playback_duration from durations of all blocks prior to seeked block.
+        // Calculate the position from durations of all blocks prior to seeked block.
         let mut new_playback_duration = Duration::ZERO;
         for (i, &block_duration) in self.block_durations.iter().enumerate() {
             if i == bounded_block_index {
@@ -227,25 +255,152 @@ impl<'a> Player<'a> {
             }
             new_playback_duration += block_duration;
         }
-        self.playback_duration = new_playback_duration;
+        self.seek(new_playback_duration);
+    }
+
+    /// Seeks to the given position from the start of the tape, which may be part way through a waveform. Positions
+    /// beyond the end of the tape seek to the start of the last waveform.
+    ///
+    /// As for [.seek_block()](Player::seek_block), playback is paused, and continues from the position once resumed.
+    pub fn seek(&mut self, position: Duration) {
+        // Do not attempt to seek while already seeking.
+        if self.is_seeking { return; }
+        self.is_seeking = true;
+
+        // Pause playback before seeking. We don't auto resume afterwards, the user is expected
+        // to manually unpause once they have seeked to the desired position.
+        // We also stop and clear the sink ready for rebuild.
+        self.is_paused = true;
+        self.sink.stop();
+        self.sink.clear();
+
+        let last_waveform_index = self.waveforms.len().saturating_sub(1);
+        let position = if position < self.total_duration { position } else { self.waveform_start(last_waveform_index) };
+        self.playback_duration = position;
 
         // Recalculate indices based on new playback_duration
         self.update_current_indices();
 
-        // Rebuild sink using all waveforms from the current index onwards.
-        self.waveform_queued_index = self.current_waveform_index;
+        // The sink is rebuilt from the start of the current waveform, and is seeked to the position within it when
+        // playback resumes.
+        self.requeue();
+
+        self.is_seeking = false;
+    }
+
+    /// Seeks to the start of the nearest pilot tone at or before the current waveform, so that a loader can
+    /// synchronise again. The pilot tone may be in an earlier block, e.g. a [PureTone](crate::tzx::blocks::PureTone)
+    /// before the data of a custom loader. Seeks to the start of the current block if there is no earlier pilot tone.
+    pub fn seek_pilot(&mut self) {
+        let end = self.waveforms_original.len().min(self.current_waveform_index + 1);
+        let pilot_index = self.waveforms_original[..end].iter().rposition(|waveform| waveform.is_pilot());
+        match pilot_index {
+            Some(index) => self.seek(self.waveform_start(index)),
+            None => self.seek_block(self.current_block_index),
+        }
+    }
+
+    /// Returns the groups containing the current block, outermost first.
+    pub fn current_groups(&self) -> Vec<&BlockGroup> {
+        return self.groups.iter().filter(|group| group.contains(self.current_block_index)).collect();
+    }
+
+    /// Seeks to the start of the next group after the current block, if there is one.
+    pub fn seek_next_group(&mut self) {
+        if let Some(group) = self.groups.iter().find(|group| group.start > self.current_block_index) {
+            self.seek_block(group.start);
+        }
+    }
+
+    /// Seeks to the start of the closest group starting before the current position, which is the start of the
+    /// current group if the current block is within one and playback has moved past its start.
+    ///
+    /// Group start blocks have no duration, so after seeking to a group its first block with a signal is the current
+    /// block. Groups are therefore compared by the position of their start rather than by block index.
+    pub fn seek_previous_group(&mut self) {
+        let elapsed = self.elapsed();
+        let group = self.groups.iter().rev()
+            .find(|group| group.start <= self.current_block_index && self.block_start(group.start) < elapsed);
+        if let Some(group) = group {
+            self.seek_block(group.start);
+        }
+    }
+
+    /// Returns the position of the start of the block at the given index.
+    fn block_start(&self, block_index: usize) -> Duration {
+        return self.block_durations[..block_index].iter().sum();
+    }
+
+    /// Returns the position of the start of the waveform at the given index.
+    fn waveform_start(&self, waveform_index: usize) -> Duration {
+        return self.waveform_durations[..waveform_index].iter().sum();
+    }
+
+    /// Rebuilds the sink using all waveforms from the current index onwards.
+    ///
+    /// The waveforms are played as a single [WaveformQueue], shaped, levelled and spread across channels as
+    /// configured, so that edges between waveforms are shaped as they are when converting.
+    fn requeue(&mut self) {
         for i in self.current_waveform_index..self.waveforms.len() {
             if self.waveforms[i].started() {
                 self.waveforms[i] = self.waveforms_original[i].clone();
             }
-            if self.waveform_queued_index < self.current_waveform_index + 1000 {
-                self.waveform_queued_index += 1;
-                let source: Box<dyn Source + Send> = self.waveforms[i].clone();
-                self.sink.append(source);
+        }
+
+        let mut queued = QueuedWaveforms { waveforms: VecDeque::new(), next_index: self.current_waveform_index };
+        self.waveform_queued_index = self.current_waveform_index;
+        self.queue_ahead(&mut queued);
+        self.queue = Arc::new(Mutex::new(queued));
+
+        let source = WaveformQueue { sample_rate: self.config.sample_rate, current: None, queued: self.queue.clone() };
+        self.sink.append(self.config.channels.apply(self.levels.apply(Shaper::new(source, &self.config))));
+    }
+
+    /// Changes the playback duration adjustment of all blocks that have yet to start playing by the given number of
+    /// percentage points, without interrupting playback.
+    ///
+    /// The block being played keeps its timings, as do earlier blocks, so the elapsed time remains correct: seek back
+    /// to a block to play it with the new timings. Adjustments are limited to no less than -90%.
+    pub fn change_playback_duration_percent(&mut self, change: i32) {
+        if self.is_seeking { return; }
+
+        // Playback runs ahead of the elapsed time by up to the buffer length, so the waveforms it has taken from the
+        // queue tell which blocks have started. Holding the lock stops it taking more until the queue is updated.
+        let queue = self.queue.clone();
+        let mut queued = queue.lock().unwrap();
+        let elapsed = self.elapsed();
+        let mut block_start = Duration::ZERO;
+        let mut first_block = self.blocks.len();
+        for (index, block_duration) in self.block_durations.iter().enumerate() {
+            if self.block_waveform_indices[index] >= queued.next_index && block_start >= elapsed {
+                first_block = index;
+                break;
             }
+            block_start += *block_duration;
         }
 
-        self.is_seeking = false;
+        let mut config = (*self.config).clone();
+        for index in first_block..self.blocks.len() {
+            let percent = (self.playback_duration_percent(index) + change).max(MIN_PLAYBACK_DURATION_PERCENT);
+            config.block_playback_duration_percent.insert(index, percent);
+        }
+        self.config = Arc::new(config);
+
+        // Regenerate the waveforms of the blocks that have yet to start, and replace any that are already queued.
+        let first_waveform = self.block_waveform_indices.get(first_block).copied().unwrap_or(self.waveforms.len());
+        self.blocks.truncate(first_block);
+        self.block_durations.truncate(first_block);
+        self.block_waveform_indices.truncate(first_block);
+        self.waveforms.truncate(first_waveform);
+        self.waveforms_original.truncate(first_waveform);
+        self.waveform_durations.truncate(first_waveform);
+        self.total_duration = self.block_durations.iter().sum();
+        self.read_blocks(first_block);
+
+        let unchanged = first_waveform - queued.next_index;
+        queued.waveforms.truncate(unchanged);
+        self.waveform_queued_index = self.waveform_queued_index.min(first_waveform);
+        self.queue_ahead(&mut queued);
     }
 
     pub fn finish(&mut self) {
@@ -260,6 +415,222 @@ impl<'a> Player<'a> {
 
     pub fn len_blocks(&self) -> usize { self.blocks.len() }
 
+    /// Returns the playback duration adjustment percentage for the block at the given index, including any
+    /// per-block override.
+    pub fn playback_duration_percent(&self, block_index: usize) -> i32 {
+        return self.config.for_block(block_index).playback_duration_percent;
+    }
+
     pub fn is_finished(&self) -> bool { self.sink.empty() }
     pub fn is_paused(&self) -> bool { self.is_paused }
 }
+
+/// The waveforms queued by a [Player] for a [WaveformQueue], shared between them so that the player can replace
+/// waveforms that have yet to be played.
+#[derive(Default)]
+struct QueuedWaveforms {
+    /// The waveforms yet to be played, in order.
+    waveforms: VecDeque<Box<dyn Waveform + Send>>,
+    /// The index in the player's waveforms of the next waveform to be played, i.e. the first that is queued.
+    next_index: usize,
+}
+
+/// Plays the waveforms queued by a [Player] one after another as a single continuous source.
+///
+/// Playback ends when the current waveform ends and no further waveform has been queued, so the player keeps
+/// waveforms queued well ahead of playback.
+struct WaveformQueue {
+    sample_rate: SampleRate,
+    current: Option<Box<dyn Waveform + Send>>,
+    queued: Arc<Mutex<QueuedWaveforms>>,
+}
+
+impl WaveformQueue {
+    /// Takes the next queued waveform to play, if there is one.
+    fn take_next(&mut self) -> Option<Box<dyn Waveform + Send>> {
+        let mut queued = self.queued.lock().unwrap();
+        let waveform = queued.waveforms.pop_front()?;
+        queued.next_index += 1;
+        return Some(waveform);
+    }
+}
+
+impl Iterator for WaveformQueue {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(sample) = self.current.as_mut().and_then(|waveform| waveform.next()) {
+                return Some(sample);
+            }
+            self.current = Some(self.take_next()?);
+        }
+    }
+}
+
+impl Source for WaveformQueue {
+    fn channels(&self) -> ChannelCount { 1 }
+    fn sample_rate(&self) -> SampleRate { self.sample_rate }
+    fn current_span_len(&self) -> Option<usize> { None }
+    fn total_duration(&self) -> Option<Duration> { None }
+
+    /// Seeks within the current waveform, which is the first waveform if playback has not started.
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        if self.current.is_none() {
+            self.current = self.take_next();
+        }
+        return match self.current.as_mut() {
+            Some(waveform) => waveform.try_seek(pos),
+            None => Ok(()),
+        };
+    }
+}
+
+/// Returns the groups of the given blocks, matching each [GroupEnd] with the most recent unmatched [GroupStart].
+///
+/// The TZX specification does not allow groups to be nested, but nested groups are handled for files that nest them
+/// anyway. Unmatched [GroupEnd] blocks are ignored, and groups that are not ended extend to the last block.
+pub fn block_groups(blocks: &[Box<dyn Block>]) -> Vec<BlockGroup> {
+    let mut groups = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+
+    for (index, block) in blocks.iter().enumerate() {
+        if let Some(group_start) = block.as_any().downcast_ref::<GroupStart>() {
+            open.push(groups.len());
+            groups.push(BlockGroup { name: group_start.name(), start: index, end: index, depth: open.len() - 1 });
+        } else if block.as_any().is::<GroupEnd>() && let Some(group_index) = open.pop() {
+            groups[group_index].end = index;
+        }
+    }
+    for group_index in open {
+        groups[group_index].end = blocks.len().saturating_sub(1);
+    }
+
+    return groups;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tzx::{
+        Header,
+        blocks::{PureTone, TurboSpeedDataBlock, TurboSpeedTimings},
+        data::DataPayload,
+        output::OutputShape,
+    };
+
+    fn waveforms(config: &Arc<Config>) -> Vec<Box<dyn Waveform + Send>> {
+        let timings = TurboSpeedTimings { length_tone_pilot: 51, ..TurboSpeedTimings::default() };
+        let turbo = TurboSpeedDataBlock::with_timings(timings, 1, DataPayload::new(8, Arc::new(vec![0x5a, 0xc3])));
+        let mut waveforms = PureTone::new(1000, 7).get_waveforms(config.clone(), true);
+        waveforms.extend(turbo.get_waveforms(config.clone(), PureTone::new(1000, 7).next_block_start_pulse_high(config.clone(), true)));
+        return waveforms;
+    }
+
+    fn queue(waveforms: &[Box<dyn Waveform + Send>]) -> WaveformQueue {
+        let queued = QueuedWaveforms { waveforms: waveforms.iter().cloned().collect(), next_index: 0 };
+        return WaveformQueue { sample_rate: 44100, current: None, queued: Arc::new(Mutex::new(queued)) };
+    }
+
+    #[test]
+    fn queued_waveforms_are_shaped_as_a_single_stream() {
+        for output_shape in [OutputShape::Blep, OutputShape::Trapezoid, OutputShape::Sine] {
+            let config = Arc::new(Config::builder().output_shape(output_shape).build());
+            let waveforms = waveforms(&config);
+
+            let stream = waveforms.clone().into_iter().flat_map(|waveform| -> Box<dyn Source + Send> { waveform });
+            let expected: Vec<f32> = Shaper::new(stream, &config).collect();
+            let played: Vec<f32> = Shaper::new(queue(&waveforms), &config).collect();
+            assert_eq!(played, expected, "{}", output_shape);
+        }
+    }
+
+    #[test]
+    fn seeking_before_playback_seeks_the_first_waveform() {
+        let config = Arc::new(Config::builder().build());
+        let waveforms = waveforms(&config);
+        let position = Duration::from_secs_f64(100.0 / 44100.0);
+
+        let mut first = waveforms[0].clone();
+        first.try_seek(position).unwrap();
+        let expected: Vec<f32> = first.chain(waveforms[1..].iter().flat_map(|waveform| waveform.clone())).collect();
+
+        let mut queue = queue(&waveforms);
+        queue.try_seek(position).unwrap();
+        assert_eq!(queue.collect::<Vec<f32>>(), expected);
+    }
+
+    #[test]
+    fn changing_the_playback_duration_replaces_only_blocks_yet_to_start() {
+        let config = Arc::new(Config::builder().build());
+        let tone: Box<dyn Block> = Box::new(PureTone::new(1000, 100));
+        let tzx_data = TzxData { header: Header::default(), blocks: vec![tone.clone(), tone.clone(), tone] };
+        let block_len = 100 * 1000 * 44100 / 3500000;
+
+        let (sink, mut output) = Sink::new();
+        let mut player = Player::new(sink, config, &tzx_data);
+        player.play();
+
+        // Playback takes the second block's waveform before the elapsed time reaches it.
+        let mut played: Vec<f32> = output.by_ref().take(block_len + 10).collect();
+        player.change_playback_duration_percent(50);
+        played.extend(output.by_ref().take(block_len * 5 / 2 + 10));
+
+        assert_eq!(player.playback_duration_percent(1), 0);
+        assert_eq!(player.playback_duration_percent(2), 50);
+        assert_eq!(player.block_durations[2], player.block_durations[0] * 3 / 2);
+        let signal_len = played.iter().rposition(|sample| *sample != 0.0).unwrap() + 1;
+        assert_eq!(signal_len, block_len * 7 / 2);
+    }
+
+    #[test]
+    fn changing_the_playback_duration_while_paused_at_a_block_start_includes_the_block() {
+        let config = Arc::new(Config::builder().build());
+        let tzx_data = TzxData { header: Header::default(), blocks: vec![Box::new(PureTone::new(1000, 100))] };
+
+        let (sink, mut output) = Sink::new();
+        let mut player = Player::new(sink, config, &tzx_data);
+        player.change_playback_duration_percent(50);
+        player.play();
+
+        let played: Vec<f32> = output.by_ref().take(2000).collect();
+        assert_eq!(player.playback_duration_percent(0), 50);
+        assert_eq!(played.iter().rposition(|sample| *sample != 0.0).unwrap() + 1, 1890);
+    }
+
+    #[test]
+    fn seeking_an_empty_tape_stays_at_the_start() {
+        let tzx_data = TzxData { header: Header::default(), blocks: vec![] };
+        let (sink, output) = Sink::new();
+        let mut player = Player::new(sink, Arc::new(Config::builder().build()), &tzx_data);
+        // Seeking clears the sink, which waits for its output to be played.
+        thread::spawn(move || output.take(1_000_000).for_each(drop));
+
+        player.seek(Duration::from_secs(1));
+        player.seek_pilot();
+        player.seek_block(3);
+        assert_eq!(player.elapsed(), Duration::ZERO);
+        assert_eq!(player.progress_in_current_waveform(), (Duration::ZERO, Duration::ZERO));
+    }
+
+    #[test]
+    fn seeking_to_the_previous_group_from_the_start_of_a_group() {
+        let group = |name: &str| -> Vec<Box<dyn Block>> {
+            vec![Box::new(GroupStart::new(name)), Box::new(PureTone::new(1000, 100)), Box::new(GroupEnd::new())]
+        };
+        let tzx_data = TzxData { header: Header::default(), blocks: [group("Loader"), group("Game")].concat() };
+        let (sink, output) = Sink::new();
+        let mut player = Player::new(sink, Arc::new(Config::builder().build()), &tzx_data);
+        // Seeking clears the sink, which waits for its output to be played.
+        thread::spawn(move || output.take(1_000_000).for_each(drop));
+
+        // Seeking pauses playback, so the elapsed time is then exactly the position seeked to.
+        player.play();
+        player.seek_block(4);
+        player.seek(player.elapsed() + Duration::from_millis(10));
+        player.seek_previous_group();
+        assert_eq!(player.elapsed(), player.block_durations[1]);
+        player.seek_previous_group();
+        assert_eq!(player.elapsed(), Duration::ZERO);
+    }
+}
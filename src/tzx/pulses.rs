@@ -0,0 +1,327 @@
+//! Pulse level streams of TZX data.
+//!
+//! [Waveform](crate::tzx::waveforms::Waveform)s generate samples for a given sample rate, rounding each pulse to a
+//! whole number of samples. Pulse streams instead describe the signal exactly, as a sequence of pulses each with a
+//! length in ZX Spectrum t cycles and a level, for use by emulators and analysis tools.
+
+use std::sync::Arc;
+
+use crate::tzx::{
+    Config,
+    blocks::{
+        Block,
+        CallSequence,
+        JumpToBlock,
+        LoopEnd,
+        LoopStart,
+        PauseOrStopTapeCommand,
+        ReturnFromSequence,
+        StopTapeIf48K,
+    },
+    waveforms::Pulse,
+};
+
+/// The number of ZX Spectrum t cycles in one millisecond, used for pause lengths.
+pub const T_CYCLES_PER_MS: u32 = 3500;
+
+/// The maximum number of consecutive blocks visited by [TapePulses] without producing a pulse before the tape is
+/// considered to have ended, guarding against flow control that loops without playing anything.
+const MAX_BLOCKS_WITHOUT_PULSES: usize = 1 << 20;
+
+/// A stream of pulses as lengths in ZX Spectrum t cycles and levels, with `true` indicating a high pulse.
+pub type PulseStream = Box<dyn Iterator<Item = (u32, bool)> + Send>;
+
+/// Returns a pulse stream of alternating pulses of the same length, as used for pilot tones.
+pub(crate) fn tone(length_pulse: u16, length_tone: u16, start_pulse_high: bool) -> impl Iterator<Item = (u32, bool)> + Send {
+    (0..length_tone).map(move |index| (length_pulse as u32, start_pulse_high ^ (index % 2 == 1)))
+}
+
+/// Returns a pulse stream of alternating pulses of the given lengths.
+pub(crate) fn sequence(pulse_lengths: Vec<u16>, start_pulse_high: bool) -> impl Iterator<Item = (u32, bool)> + Send {
+    pulse_lengths.into_iter()
+        .enumerate()
+        .map(move |(index, length)| (length as u32, start_pulse_high ^ (index % 2 == 1)))
+}
+
+/// Converts an iterator of [Pulse]s, as used by waveforms, to a pulse stream.
+pub(crate) fn from_pulses(pulses: impl Iterator<Item = Pulse> + Send) -> impl Iterator<Item = (u32, bool)> + Send {
+    pulses.map(|pulse| (pulse.length as u32, pulse.high))
+}
+
+/// Returns the [Config] used to construct pulse iterators for waveforms. Pulse iterators only use the configuration
+/// for sample timings, so the default suffices.
+pub(crate) fn pulse_config() -> Arc<Config> { Arc::new(Config::default()) }
+
+/// Returns a pulse stream for a pause of the given length in milliseconds, with no preceding edge.
+pub(crate) fn pause(pause: u16) -> PulseStream {
+    Box::new((pause > 0).then_some((pause as u32 * T_CYCLES_PER_MS, false)).into_iter())
+}
+
+/// Appends a pause of the given length in milliseconds to a pulse stream.
+///
+/// As described in the [specification](https://worldofspectrum.net/TZXformat.html#TURBOSPEED), if the last pulse
+/// is low, the pause starts with one millisecond at the opposite level so that the last pulse is terminated by an
+/// edge. The remainder of the pause is low.
+pub(crate) fn with_pause(pulses: impl Iterator<Item = (u32, bool)> + Send + 'static, pause: u16) -> PulseStream {
+    Box::new(PauseAfter { pulses, pause, last_pulse_high: None, pause_pulses: Vec::new() })
+}
+
+struct PauseAfter<I> {
+    pulses: I,
+    pause: u16,
+    last_pulse_high: Option<bool>,
+    pause_pulses: Vec<(u32, bool)>,
+}
+
+impl<I: Iterator<Item = (u32, bool)>> Iterator for PauseAfter<I> {
+    type Item = (u32, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pause > 0 {
+            if let Some(pulse) = self.pulses.next() {
+                self.last_pulse_high = Some(pulse.1);
+                return Some(pulse);
+            }
+
+            let length = self.pause as u32 * T_CYCLES_PER_MS;
+            self.pause_pulses = match self.last_pulse_high {
+                Some(false) => vec![(length - T_CYCLES_PER_MS, false), (T_CYCLES_PER_MS, true)],
+                _ => vec![(length, false)],
+            };
+            self.pause_pulses.retain(|(length, _)| *length > 0);
+            self.pause = 0;
+        }
+        return self.pulses.next().or_else(|| self.pause_pulses.pop());
+    }
+}
+
+/// Iterates the pulses of [TzxData](crate::tzx::TzxData) with flow control applied, as returned by
+/// [TzxData::pulses](crate::tzx::TzxData::pulses).
+///
+/// Blocks are visited in playback order: [LoopStart] / [LoopEnd], [JumpToBlock] and [CallSequence] /
+/// [ReturnFromSequence] blocks are followed, while 'stop the tape' commands and [SelectBlock](crate::tzx::blocks::SelectBlock)s
+/// are passed over, so the tape plays through to the end unless [.stop_at_stop_tape()](TapePulses::stop_at_stop_tape)
+/// is used. Consecutive pulses at the same level are combined, so
+/// every pulse is followed by an edge, and zero length pulses are dropped. Pulse lengths are given as `u64` since
+/// combined pulses, e.g. consecutive pauses, may exceed the range of a `u32`.
+pub struct TapePulses {
+    blocks: Vec<Box<dyn Block>>,
+    next_block_index: Option<usize>,
+    current_block_index: Option<usize>,
+    current_pulses: Option<PulseStream>,
+    start_pulse_high: bool,
+    last_pulse_high: Option<bool>,
+    pending: Option<(u64, bool, usize)>,
+    pulse_block_index: Option<usize>,
+    loops: Vec<(usize, u16)>,
+    calls: Vec<(usize, Vec<i16>, usize)>,
+    blocks_without_pulses: usize,
+    stop_tape: bool,
+    stop_tape_if_48k: bool,
+    stopped_at: Option<usize>,
+}
+
+impl TapePulses {
+    pub fn new(blocks: Vec<Box<dyn Block>>) -> Self {
+        return Self {
+            next_block_index: if blocks.is_empty() { None } else { Some(0) },
+            blocks,
+            current_block_index: None,
+            current_pulses: None,
+            start_pulse_high: true,
+            last_pulse_high: None,
+            pending: None,
+            pulse_block_index: None,
+            loops: Vec::new(),
+            calls: Vec::new(),
+            blocks_without_pulses: 0,
+            stop_tape: false,
+            stop_tape_if_48k: false,
+            stopped_at: None,
+        }
+    }
+
+    /// Stops iteration at 'stop the tape' commands, and also at [StopTapeIf48K] blocks if `stop_tape_if_48k` is set.
+    ///
+    /// When a stop is reached, iteration returns `None` with [.stopped_at()](TapePulses::stopped_at) giving the index
+    /// of the block, until [.resume()](TapePulses::resume) is called.
+    pub fn stop_at_stop_tape(mut self, stop_tape_if_48k: bool) -> Self {
+        self.stop_tape = true;
+        self.stop_tape_if_48k = stop_tape_if_48k;
+        return self;
+    }
+
+    /// Returns the index of the block at which iteration has stopped, if any.
+    pub fn stopped_at(&self) -> Option<usize> { self.stopped_at }
+
+    /// Resumes iteration after a stop.
+    pub fn resume(&mut self) { self.stopped_at = None; }
+
+    /// Returns the blocks being iterated.
+    pub fn blocks(&self) -> &[Box<dyn Block>] { &self.blocks }
+
+    /// Returns the index of the next block to start, i.e. the first block for which no pulse has yet been returned.
+    pub fn upcoming_block_index(&self) -> Option<usize> {
+        return match (self.pending, self.current_block_index) {
+            (Some((_, _, block_index)), _) if Some(block_index) != self.pulse_block_index => Some(block_index),
+            (_, Some(block_index)) if Some(block_index) != self.pulse_block_index => Some(block_index),
+            _ => self.next_block_index,
+        };
+    }
+
+    /// Skips any remaining pulses up to the end of the given block, which must be the block currently playing or a
+    /// block reached from the [upcoming block](TapePulses::upcoming_block_index) without flow control. Iteration
+    /// continues with the block following it.
+    pub fn skip_block(&mut self, block_index: usize) {
+        self.pending = None;
+        self.current_pulses = None;
+        self.current_block_index = None;
+        self.stopped_at = None;
+        self.pulse_block_index = Some(block_index);
+        self.start_pulse_high = self.blocks[block_index].next_block_start_pulse_high(pulse_config(), true);
+        self.next_block_index = self.following_block_index(block_index);
+    }
+
+    /// Returns the index of the block in which the last pulse returned started.
+    pub fn block_index(&self) -> Option<usize> { self.pulse_block_index }
+
+    /// Returns the next pulse of the current block, moving on to subsequent blocks as each is exhausted, together with
+    /// the index of the block containing it.
+    fn next_block_pulse(&mut self) -> Option<(u32, bool, usize)> {
+        if self.stopped_at.is_some() { return None }
+
+        loop {
+            if let (Some(pulses), Some(block_index)) = (self.current_pulses.as_mut(), self.current_block_index) {
+                if let Some((length, high)) = pulses.next() {
+                    self.last_pulse_high = Some(high);
+                    self.blocks_without_pulses = 0;
+                    return Some((length, high, block_index));
+                }
+                self.finish_block(block_index);
+            }
+
+            let block_index = self.next_block_index?;
+            self.blocks_without_pulses += 1;
+            if self.blocks_without_pulses > MAX_BLOCKS_WITHOUT_PULSES {
+                self.next_block_index = None;
+                return None;
+            }
+
+            self.current_block_index = Some(block_index);
+            self.next_block_index = self.following_block_index(block_index);
+            self.current_pulses = Some(self.blocks[block_index].pulses(self.start_pulse_high));
+            self.last_pulse_high = None;
+
+            if self.is_stop(block_index) {
+                self.stopped_at = Some(block_index);
+                return None;
+            }
+        }
+    }
+
+    /// Returns true if iteration should stop at the given block.
+    fn is_stop(&self, block_index: usize) -> bool {
+        let block = self.blocks[block_index].as_any();
+        return self.stop_tape && (
+            block.downcast_ref::<PauseOrStopTapeCommand>().is_some_and(|block| block.is_stop_tape())
+            || (self.stop_tape_if_48k && block.is::<StopTapeIf48K>())
+        );
+    }
+
+    /// Determines the starting pulse state for the block following the given finished block.
+    ///
+    /// The next block starts with the opposite level to the last pulse, or as determined by the block where it has no
+    /// pulses, e.g. for [SetSignalLevel](crate::tzx::blocks::SetSignalLevel).
+    fn finish_block(&mut self, block_index: usize) {
+        self.start_pulse_high = match self.last_pulse_high {
+            Some(high) => !high,
+            None => self.blocks[block_index].next_block_start_pulse_high(pulse_config(), self.start_pulse_high),
+        };
+        self.current_pulses = None;
+        self.current_block_index = None;
+    }
+
+    /// Returns the index of the block to play after the given block, applying flow control.
+    fn following_block_index(&mut self, block_index: usize) -> Option<usize> {
+        let block = self.blocks[block_index].as_any();
+        let next = block_index + 1;
+
+        let target = if let Some(loop_start) = block.downcast_ref::<LoopStart>() {
+            self.loops.push((next, loop_start.repetitions()));
+            next
+        } else if block.is::<LoopEnd>() {
+            match self.loops.last_mut() {
+                Some((start, repetitions)) if *repetitions > 1 => {
+                    *repetitions -= 1;
+                    *start
+                }
+                Some(_) => {
+                    self.loops.pop();
+                    next
+                }
+                None => next,
+            }
+        } else if let Some(jump) = block.downcast_ref::<JumpToBlock>() {
+            match jump.offset() {
+                0 => next,
+                offset => return offset_block_index(block_index, offset, self.blocks.len()),
+            }
+        } else if let Some(call) = block.downcast_ref::<CallSequence>() {
+            match call.block_offsets().first() {
+                Some(offset) => {
+                    self.calls.push((block_index, call.block_offsets().to_vec(), 0));
+                    return offset_block_index(block_index, *offset, self.blocks.len());
+                }
+                None => next,
+            }
+        } else if block.is::<ReturnFromSequence>() {
+            match self.calls.last_mut() {
+                Some((call_index, offsets, position)) if *position + 1 < offsets.len() => {
+                    *position += 1;
+                    let (call_index, offset) = (*call_index, offsets[*position]);
+                    return offset_block_index(call_index, offset, self.blocks.len());
+                }
+                Some((call_index, _, _)) => {
+                    let call_index = *call_index;
+                    self.calls.pop();
+                    call_index + 1
+                }
+                None => next,
+            }
+        } else {
+            next
+        };
+
+        return if target < self.blocks.len() { Some(target) } else { None };
+    }
+}
+
+impl Iterator for TapePulses {
+    type Item = (u64, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut pulse = self.pending.take();
+        while let Some(next) = self.next_block_pulse() {
+            if next.0 == 0 { continue }
+            let next = (next.0 as u64, next.1, next.2);
+            match pulse {
+                Some((length, high, block_index)) if high == next.1 => pulse = Some((length + next.0, high, block_index)),
+                Some(_) => {
+                    self.pending = Some(next);
+                    break;
+                }
+                None => pulse = Some(next),
+            }
+        }
+
+        let (length, high, block_index) = pulse?;
+        self.pulse_block_index = Some(block_index);
+        return Some((length, high));
+    }
+}
+
+/// Applies a relative block offset, as used by [JumpToBlock] and [CallSequence], returning `None` if it is out of
+/// bounds.
+fn offset_block_index(block_index: usize, offset: i16, block_count: usize) -> Option<usize> {
+    block_index.checked_add_signed(offset as isize).filter(|index| *index < block_count)
+}
@@ -25,12 +25,19 @@ use std::io::{
     ErrorKind,
     Read,
     Seek,
+    SeekFrom,
     Write,
 };
 
 use crate::{
     TzxData,
-    tzx::blocks::Block,
+    tzx::{
+        ParseDiagnostic,
+        ParseError,
+        ParseMode,
+        ParseReport,
+        blocks::Block,
+    },
 };
 
 /// A payload corresponding to a known platform encoding.
@@ -223,22 +230,60 @@ impl TapData {
 
     /// Attempts to parse [TapData] from the supplied reader.
     ///
-    /// We process the data in a loop, reading a block length and flag byte and attempting to pass the next chunk of
-    /// data as a corresponding block payload type.
-    ///
-    /// block type identification byte and then attempting to parse the corresponding block data using
-    /// [read_block].
-    ///
-    /// Once a block has successfully been parsed, the reader will be aligned to the end of the block ready to read
-    /// the next length and flag byte. However, should a file be incorrectly formatted it is possible that the reader
-    /// will become incorrectly aligned, resulting in further parse errors throughout the remainder of the file.
-    ///
-    /// Any such parse errors will not cause a panic unless they cause the parser to try and do something bad like
-    /// reading beyond the end of the file.
+    /// This is equivalent to [TapData::parse] in [ParseMode::Lenient], discarding the [ParseReport]: blocks that fail
+    /// to parse are silently skipped.
     pub fn read<R: Read + Seek>(reader: & mut R) -> Result<Self, Error> {
         TapData::read_le(reader)
     }
 
+    /// Attempts to parse [TapData] from the supplied reader, returning a [ParseReport] of any problems encountered
+    /// alongside the data.
+    ///
+    /// We process the data in a loop, reading a block length and flag byte and attempting to pass the next chunk of
+    /// data as a corresponding block payload type using [read_payload]. Should a payload fail to parse, the reader is
+    /// moved to the end of the block as given by the block length, ready to read the next block length.
+    ///
+    /// In [ParseMode::Lenient], blocks that fail to parse are recorded in the report and skipped. In
+    /// [ParseMode::Strict], the first such block causes parsing to fail with [ParseError::Block]. IO errors other
+    /// than reaching the end of the data always cause parsing to fail with [ParseError::Io].
+    pub fn parse<R: Read + Seek>(reader: &mut R, mode: ParseMode) -> Result<(Self, ParseReport), ParseError> {
+        // Use a BufReader to handle underlying reads from the input.
+        let mut reader = BufReader::new(reader);
+
+        let mut blocks: Vec<Box<dyn Payload + 'static>> = Vec::new();
+        let mut report = ParseReport::new();
+
+        loop {
+            let offset = reader.stream_position()?;
+
+            let block_length = match u16::read_le(&mut reader) {
+                Ok(block_length) => block_length,
+                Err(Error::Io(why)) if why.kind() == ErrorKind::UnexpectedEof => break,
+                Err(why) => return Err(why.into()),
+            };
+
+            match read_payload(block_length as usize, true, &mut reader) {
+                Ok(payload) => blocks.push(payload),
+                Err(why) => {
+                    let diagnostic = ParseDiagnostic {
+                        block_index: blocks.len(),
+                        offset,
+                        block_type: None,
+                        error: Error::Custom { pos: offset, err: Box::new(why) },
+                        recovered: false,
+                    };
+                    if mode == ParseMode::Strict {
+                        return Err(ParseError::Block(diagnostic));
+                    }
+                    report.push(diagnostic);
+                    reader.seek(SeekFrom::Start(offset + 2 + block_length as u64))?;
+                }
+            }
+        }
+
+        return Ok((TapData { blocks }, report));
+    }
+
     // /// Writes [TapData] to the supplied writer.
     // pub fn write<W: Write + Seek>(&self, writer: & mut W) -> Result<(), Error> {
     //     self.write_le(writer)
@@ -253,48 +298,9 @@ impl BinRead for TapData {
         _endian: binrw::Endian,
         _args: Self::Args<'_>,
     ) -> BinResult<Self> {
-        // Use a BufReader to handle underlying reads from the input.
-        let mut reader = BufReader::new(reader);
-
-        let mut blocks: Vec<Box<dyn Payload + 'static>> = Vec::new();
-
-        'parse_blocks: loop {
-            let block_length_result = u16::read_le(&mut reader);
-
-            if block_length_result.is_err() {
-                match block_length_result.unwrap_err() {
-                    Error::Io(why) => match why.kind() {
-                        ErrorKind::UnexpectedEof => break 'parse_blocks,
-                        _ => panic!("IO error: {}", why),
-                    }
-                    other => eprintln!("Unhandled error: {}", other),
-                }
-                if reader.seek_relative(1).is_ok() {
-                    continue 'parse_blocks;
-                } else {
-                    break 'parse_blocks;
-                };
-            }
-            let block_length = block_length_result.unwrap();
-
-            let payload_result: Result<Box<dyn Payload>, PayloadError> = read_payload(block_length as usize, true, &mut reader);
-            let block = match payload_result {
-                Err(why) => {
-                    reader.seek(std::io::SeekFrom::Current(block_length as i64 - 1)).unwrap();
-                    eprintln!("Failed to parse after block {}: {:?}", blocks.len(), why);
-                    None
-                },
-                Ok(payload) => Some(payload),
-            };
-
-            if block.is_none() { continue }
-
-            blocks.push(block.unwrap());
-        }
-
-        return Ok(TapData {
-            blocks
-        });
+        TapData::parse(reader, ParseMode::Lenient)
+            .map(|(tap_data, _)| tap_data)
+            .map_err(Into::into)
     }
 }
 
@@ -0,0 +1,166 @@
+//! Tape deck emulation.
+
+pub mod rom_trap;
+
+pub use rom_trap::RomLoad;
+
+use crate::tzx::{
+    TapePulses,
+    TzxData,
+};
+
+/// An event reported by a [TapeDeck] as the tape plays.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum TapeDeckEvent {
+    /// The block with the given index started playing.
+    BlockStarted(usize),
+    /// The tape was stopped by the 'stop the tape' command at the given block index, turning the motor off.
+    TapeStopped(usize),
+    /// The end of the tape was reached.
+    TapeFinished,
+}
+
+/// Emulates a tape deck playing [TzxData], for use as the tape input of an emulator.
+///
+/// Rather than generating samples, the deck is advanced by a number of CPU t cycles at a time, e.g. after each
+/// emulated instruction, and returns the EAR level at that point. Timings are taken directly from the
+/// [pulses](TzxData::pulses) of the tape, so are exact and unaffected by any [Config](crate::tzx::Config).
+///
+/// The motor can be switched on and off, e.g. to emulate the CPC's cassette motor control, and the deck switches its
+/// own motor off when a 'stop the tape' command is reached. Block changes and stops are reported as
+/// [TapeDeckEvent]s, to be collected with [.take_events()](TapeDeck::take_events).
+///
+/// ```
+/// use rtzx::TzxData;
+/// use rtzx::tzx::{TapeDeck, TapeDeckEvent};
+///
+/// let tzx_data = TzxData::builder()
+///     .pure_tone(1000, 4)
+///     .stop_tape()
+///     .pure_tone(2000, 2)
+///     .build();
+///
+/// let mut deck = TapeDeck::new(&tzx_data);
+/// deck.set_motor(true);
+/// assert!(deck.advance(500));
+/// assert!(!deck.advance(1000));
+/// deck.advance(10000);
+/// assert!(!deck.motor());
+/// assert_eq!(deck.take_events(), [TapeDeckEvent::BlockStarted(0), TapeDeckEvent::TapeStopped(1)]);
+///
+/// deck.set_motor(true);
+/// assert!(deck.advance(1));
+/// ```
+pub struct TapeDeck {
+    tzx_data: TzxData,
+    stop_tape_if_48k: bool,
+    pulses: TapePulses,
+    motor: bool,
+    ear: bool,
+    pulse_remaining: u64,
+    block_index: Option<usize>,
+    pulses_in_block: usize,
+    finished: bool,
+    events: Vec<TapeDeckEvent>,
+}
+
+impl TapeDeck {
+    /// Creates a tape deck for the given data, rewound to the start with the motor off.
+    pub fn new(tzx_data: &TzxData) -> Self {
+        return Self {
+            tzx_data: tzx_data.clone(),
+            stop_tape_if_48k: false,
+            pulses: tzx_data.pulses().stop_at_stop_tape(false),
+            motor: false,
+            ear: false,
+            pulse_remaining: 0,
+            block_index: None,
+            pulses_in_block: 0,
+            finished: false,
+            events: Vec::new(),
+        }
+    }
+
+    /// Sets whether [StopTapeIf48K](crate::tzx::blocks::StopTapeIf48K) blocks stop the tape, i.e. whether the
+    /// emulated machine is in 48K mode. Rewinds the tape.
+    pub fn set_48k_mode(&mut self, is_48k: bool) {
+        self.stop_tape_if_48k = is_48k;
+        self.rewind();
+    }
+
+    /// Rewinds the tape to the start. The motor state is unchanged.
+    pub fn rewind(&mut self) {
+        self.pulses = self.tzx_data.pulses().stop_at_stop_tape(self.stop_tape_if_48k);
+        self.ear = false;
+        self.pulse_remaining = 0;
+        self.block_index = None;
+        self.pulses_in_block = 0;
+        self.finished = false;
+    }
+
+    /// Switches the motor on or off. The tape only advances while the motor is on.
+    pub fn set_motor(&mut self, on: bool) { self.motor = on; }
+
+    /// Returns whether the motor is on.
+    pub fn motor(&self) -> bool { self.motor }
+
+    /// Returns the current EAR level.
+    pub fn ear(&self) -> bool { self.ear }
+
+    /// Returns the index of the block currently playing, if any.
+    pub fn block_index(&self) -> Option<usize> { self.block_index }
+
+    /// Returns whether the end of the tape has been reached.
+    pub fn is_finished(&self) -> bool { self.finished }
+
+    /// Returns the events that have occurred since the last call, in order.
+    pub fn take_events(&mut self) -> Vec<TapeDeckEvent> { std::mem::take(&mut self.events) }
+
+    /// Advances the tape by the given number of t cycles if the motor is on, returning the EAR level.
+    ///
+    /// Where a pulse ends exactly at the end of the advance, the returned level is that following the edge.
+    pub fn advance(&mut self, tstates: u32) -> bool {
+        let mut remaining = tstates as u64;
+        while self.motor && !self.finished {
+            if self.pulse_remaining == 0 {
+                self.next_pulse();
+                continue;
+            }
+            if remaining == 0 { break }
+
+            let step = remaining.min(self.pulse_remaining);
+            self.pulse_remaining -= step;
+            remaining -= step;
+        }
+        return self.ear;
+    }
+
+    /// Moves on to the next pulse, handling stops and the end of the tape.
+    fn next_pulse(&mut self) {
+        match self.pulses.next() {
+            Some((length, high)) => {
+                self.ear = high;
+                self.pulse_remaining = length;
+                self.pulses_in_block += 1;
+                if self.pulses.block_index() != self.block_index {
+                    self.block_index = self.pulses.block_index();
+                    self.pulses_in_block = 1;
+                    if let Some(block_index) = self.block_index {
+                        self.events.push(TapeDeckEvent::BlockStarted(block_index));
+                    }
+                }
+            }
+            None => match self.pulses.stopped_at() {
+                Some(block_index) => {
+                    self.pulses.resume();
+                    self.motor = false;
+                    self.events.push(TapeDeckEvent::TapeStopped(block_index));
+                }
+                None => {
+                    self.finished = true;
+                    self.events.push(TapeDeckEvent::TapeFinished);
+                }
+            },
+        }
+    }
+}
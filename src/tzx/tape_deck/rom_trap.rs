@@ -0,0 +1,204 @@
+//! Fast loading by trapping the ZX Spectrum ROM tape loading routine.
+//!
+//! Emulators commonly speed up loading by intercepting calls to the ROM's LD-BYTES routine at 0x0556 and copying the
+//! data of the next standard block directly into memory, rather than playing the tape in real time. LD-BYTES is
+//! called with the expected flag byte in A, the length in DE and the destination address in IX, and returns with
+//! the carry flag set on success.
+
+use std::io::{Cursor, Read};
+
+use crate::tzx::{
+    blocks::{
+        Block,
+        CallSequence,
+        JumpToBlock,
+        LoopEnd,
+        LoopStart,
+        PauseOrStopTapeCommand,
+        ReturnFromSequence,
+        StandardSpeedDataBlock,
+        StopTapeIf48K,
+        TurboSpeedDataBlock,
+    },
+    data::DataPayload,
+    tap::XorReader,
+    timing::is_pause,
+    TapeDeck,
+    TapeDeckEvent,
+};
+
+/// The address of the LD-BYTES routine in the ZX Spectrum ROM.
+pub const LD_BYTES_ADDRESS: u16 = 0x0556;
+
+/// The result of fast loading a block with [TapeDeck::load_rom_block].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RomLoad {
+    /// The index of the block loaded.
+    pub block_index: usize,
+    /// The address at which the data should be written, as passed in IX.
+    pub destination: u16,
+    /// The data loaded, excluding the flag and checksum bytes. This is shorter than the requested length if the
+    /// block is too short.
+    pub data: Vec<u8>,
+    /// Whether the load succeeded, i.e. the full length was loaded and the checksum matched. LD-BYTES should return
+    /// with the carry flag set to this value.
+    pub success: bool,
+}
+
+impl RomLoad {
+    /// Returns the loaded data as pairs of addresses and bytes, starting from the destination and wrapping around
+    /// at the top of memory as the ROM routine does.
+    pub fn bytes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.data.iter().enumerate().map(|(offset, byte)| (self.destination.wrapping_add(offset as u16), *byte))
+    }
+}
+
+impl TapeDeck {
+    /// Fast loads the next [StandardSpeedDataBlock] or [TurboSpeedDataBlock] with the given flag byte, for use when
+    /// the emulated CPU reaches [LD_BYTES_ADDRESS].
+    ///
+    /// The block loaded is the block currently playing if it is still within its pilot tone, and otherwise the next
+    /// block to be played. Data blocks with a different flag byte are skipped over, as the ROM would do when
+    /// searching for a header. Up to `length` bytes are read from the block and the checksum is verified with an
+    /// [XorReader]. The tape is then positioned at the start of the block following the one loaded.
+    ///
+    /// Pauses between blocks are skipped over too. Returns `None` without changing the tape position if any other
+    /// block that is not a standard or turbo speed data block is reached first, e.g. for a custom loader, or if the
+    /// tape is stopped or finished. The emulator should then
+    /// continue running the ROM routine with the tape playing in real time.
+    ///
+    /// ```
+    /// use rtzx::TzxData;
+    /// use rtzx::tzx::TapeDeck;
+    ///
+    /// let tzx_data = TzxData::builder()
+    ///     .standard_block(vec![0x00, 0x01, 0x02, 0x03])
+    ///     .standard_block(vec![0xff, 0xaa, 0xbb, 0xee])
+    ///     .pure_tone(1000, 100)
+    ///     .build();
+    ///
+    /// let mut deck = TapeDeck::new(&tzx_data);
+    /// let load = deck.load_rom_block(0xff, 2, 0x8000).unwrap();
+    /// assert_eq!(load.block_index, 1);
+    /// assert_eq!(load.bytes().collect::<Vec<_>>(), [(0x8000, 0xaa), (0x8001, 0xbb)]);
+    /// assert!(load.success);
+    ///
+    /// // The pure tone is not a standard block, so must be played in real time.
+    /// assert!(deck.load_rom_block(0xff, 2, 0x8000).is_none());
+    /// ```
+    pub fn load_rom_block(&mut self, flag: u8, length: u16, destination: u16) -> Option<RomLoad> {
+        let block_index = self.find_rom_block(flag)?;
+        let payload = rom_payload(self.pulses.blocks()[block_index].as_ref())?;
+
+        let mut reader = XorReader::new(Cursor::new(payload.data.as_slice()), 0);
+        let mut flag_byte = [0u8];
+        reader.read_exact(&mut flag_byte).ok()?;
+
+        // Read as much of the requested length as the block holds, followed by the checksum byte if present.
+        let available = payload.len() - 1;
+        let mut data = vec![0u8; available.min(length as usize)];
+        reader.read_exact(&mut data).ok()?;
+        let mut checksum = [0u8];
+        let has_checksum = reader.read_exact(&mut checksum).is_ok();
+        let success = data.len() == length as usize && has_checksum && reader.xor() == 0;
+
+        self.pulses.skip_block(block_index);
+        self.pulse_remaining = 0;
+        self.ear = false;
+        if self.block_index != Some(block_index) {
+            self.block_index = Some(block_index);
+            self.events.push(TapeDeckEvent::BlockStarted(block_index));
+        }
+        // No pulses of the loaded block remain, so it cannot be loaded again.
+        self.pulses_in_block = 0;
+
+        return Some(RomLoad { block_index, destination, data, success });
+    }
+
+    /// Finds the index of the next data block with the given flag byte that can be fast loaded.
+    fn find_rom_block(&self, flag: u8) -> Option<usize> {
+        if self.finished { return None }
+
+        let blocks = self.pulses.blocks();
+        let in_pilot = |block_index: usize| rom_pilot_pulses(blocks[block_index].as_ref())
+            .is_some_and(|pilot_pulses| (1..=pilot_pulses).contains(&self.pulses_in_block));
+
+        let mut block_index = match self.block_index {
+            Some(block_index) if in_pilot(block_index) => block_index,
+            _ => self.pulses.upcoming_block_index()?,
+        };
+
+        loop {
+            let block = blocks[block_index].as_ref();
+            if let Some(payload) = rom_payload(block) {
+                if payload.data.first() == Some(&flag) {
+                    return Some(block_index);
+                }
+            } else if !is_pause(block) && (is_flow_control(block) || block.pulses(true).next().is_some()) {
+                return None;
+            }
+            block_index += 1;
+            if block_index >= blocks.len() { return None }
+        }
+    }
+}
+
+/// Returns the payload of a block that can be fast loaded.
+fn rom_payload(block: &dyn Block) -> Option<&DataPayload> {
+    if let Some(block) = block.as_any().downcast_ref::<StandardSpeedDataBlock>() {
+        return Some(&block.payload).filter(|payload| payload.len() > 0);
+    }
+    if let Some(block) = block.as_any().downcast_ref::<TurboSpeedDataBlock>() {
+        return Some(&block.payload).filter(|payload| payload.len() > 0);
+    }
+    return None;
+}
+
+/// Returns the number of pilot pulses of a block that can be fast loaded.
+fn rom_pilot_pulses(block: &dyn Block) -> Option<usize> {
+    if let Some(block) = block.as_any().downcast_ref::<StandardSpeedDataBlock>() {
+        return Some(if block.payload.data.first().is_some_and(|flag| *flag < 128) { 8063 } else { 3223 });
+    }
+    if let Some(block) = block.as_any().downcast_ref::<TurboSpeedDataBlock>() {
+        return Some(block.length_tone_pilot as usize);
+    }
+    return None;
+}
+
+/// Returns true for blocks that change the order of playback or stop the tape, past which blocks cannot be searched
+/// without playing the tape.
+fn is_flow_control(block: &dyn Block) -> bool {
+    let block = block.as_any();
+    return block.is::<LoopStart>()
+        || block.is::<LoopEnd>()
+        || block.is::<JumpToBlock>()
+        || block.is::<CallSequence>()
+        || block.is::<ReturnFromSequence>()
+        || block.is::<StopTapeIf48K>()
+        || block.downcast_ref::<PauseOrStopTapeCommand>().is_some_and(|block| block.is_stop_tape());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TzxData;
+    use crate::tzx::TapeDeck;
+
+    #[test]
+    fn pauses_between_blocks_do_not_prevent_fast_loading() {
+        let tzx_data = TzxData::builder()
+            .standard_block(vec![0x00, 0x01, 0x02, 0x03])
+            .pause(1000)
+            .standard_block(vec![0xff, 0xaa, 0xbb, 0xee])
+            .pause(0)
+            .standard_block(vec![0xff, 0xcc, 0xdd, 0x11])
+            .build();
+
+        let mut deck = TapeDeck::new(&tzx_data);
+        let load = deck.load_rom_block(0xff, 2, 0x8000).unwrap();
+        assert_eq!(load.block_index, 2);
+        assert!(load.success);
+
+        // A 'stop tape' command still stops the search.
+        assert!(deck.load_rom_block(0xff, 2, 0x8000).is_none());
+    }
+}
@@ -0,0 +1,190 @@
+//! Adjustment of the silences and pilot tones of a tape for playback on real machines.
+//!
+//! Files made for emulators often start with long pauses, or have short pilot tones and gaps that an emulator copes
+//! with but a real machine, which needs time to settle after PLAY is pressed or after a block has loaded, does not.
+//! [adjust_timings] rewrites the blocks of a tape according to the timing options of a [Config] before conversion or
+//! playback, and [block_waveforms] plays them with the configured initial silence.
+
+use std::sync::Arc;
+
+use crate::tzx::{
+    Config,
+    TzxData,
+    blocks::{
+        Block,
+        DirectRecording,
+        GeneralizedDataBlock,
+        KansasCityStandardDataBlock,
+        PauseOrStopTapeCommand,
+        PureDataBlock,
+        StandardSpeedDataBlock,
+        TurboSpeedDataBlock,
+        TurboSpeedTimings,
+    },
+    waveforms::{PauseType, PauseWaveform, Waveform},
+};
+
+/// Returns a copy of the data with the [Config]'s minimum pilot length and minimum gap applied. Blocks are only
+/// changed in place, so block numbers and the offsets of flow control blocks are unchanged.
+///
+/// - `min_pilot_pulses` lengthens the pilot tones of [StandardSpeedDataBlock]s and [TurboSpeedDataBlock]s with
+///   fewer pulses. Standard speed blocks are replaced with turbo speed blocks with standard timings, as their pilot
+///   length is fixed.
+/// - `min_gap_ms` lengthens pauses that are shorter. Zero length pauses are left unchanged, as loaders that run
+///   blocks together depend on there being no gap, and a zero length pause block is a 'stop tape' command.
+///
+/// The initial silence is applied when the blocks are played, by [block_waveforms].
+///
+/// ```
+/// use rtzx::TzxData;
+/// use rtzx::tzx::{Config, timing::adjust_timings};
+///
+/// let tzx_data = TzxData::builder()
+///     .pause(5000)
+///     .standard_block(vec![0xff, 0xaa])
+///     .build();
+///
+/// let config = Config::builder().min_pilot_pulses(5000).build();
+/// let adjusted = adjust_timings(&tzx_data, &config);
+/// assert_eq!(adjusted.blocks[0].to_string(), "PauseOrStopTapeCommand: pause 5000ms");
+/// assert!(adjusted.blocks[1].to_string().starts_with("TurboSpeedDataBlock"));
+/// ```
+pub fn adjust_timings(tzx_data: &TzxData, config: &Config) -> TzxData {
+    let mut blocks = tzx_data.blocks.clone();
+
+    for block in blocks.iter_mut() {
+        if let Some(min_pilot_pulses) = config.min_pilot_pulses {
+            lengthen_pilot(block, min_pilot_pulses);
+        }
+        if let Some(min_gap_ms) = config.min_gap_ms {
+            lengthen_pause(block.as_mut(), min_gap_ms);
+        }
+    }
+
+    return TzxData { header: tzx_data.header.clone(), blocks };
+}
+
+/// Returns the waveforms of each of the given blocks in turn from the block at `first_block` onwards, with the
+/// [Config]'s initial silence applied.
+///
+/// If `initial_silence_ms` is set, the pauses before the first block with a signal are not played, and the first
+/// block starts with a pause of that length instead, if it is not zero. The blocks themselves are left unchanged, as
+/// a zero length pause block would be a 'stop tape' command, and adding or removing blocks would renumber them.
+pub fn block_waveforms<'a>(
+    blocks: &'a [Box<dyn Block>],
+    config: &Arc<Config>,
+    first_block: usize
+) -> impl Iterator<Item = Vec<Box<dyn Waveform + Send>>> + 'a {
+    let first_signal = match config.initial_silence_ms {
+        Some(_) => first_signal(blocks),
+        None => 0,
+    };
+    let initial_silence_ms = config.initial_silence_ms.unwrap_or(0);
+    let config = config.clone();
+    let mut start_pulse_high = blocks[..first_block].iter().enumerate()
+        .fold(true, |high, (index, block)| block.next_block_start_pulse_high(config.for_block(index), high));
+
+    return blocks.iter().enumerate().skip(first_block).map(move |(index, block)| {
+        let block_config = config.for_block(index);
+        let mut waveforms = if index < first_signal && is_pause(block.as_ref()) {
+            Vec::new()
+        } else {
+            block.get_waveforms(block_config.clone(), start_pulse_high)
+        };
+        if index == 0 && initial_silence_ms > 0 {
+            let silence = PauseWaveform::new(block_config.clone(), initial_silence_ms, PauseType::Zero);
+            waveforms.insert(0, Box::new(silence));
+        }
+        start_pulse_high = block.next_block_start_pulse_high(block_config, start_pulse_high);
+        return waveforms;
+    });
+}
+
+/// Returns whether the block is a pause, rather than a 'stop tape' command.
+pub(crate) fn is_pause(block: &dyn Block) -> bool {
+    return block.as_any().downcast_ref::<PauseOrStopTapeCommand>().is_some_and(|pause| !pause.is_stop_tape());
+}
+
+/// Returns the index of the first block with a signal, or the number of blocks if there is none.
+fn first_signal(blocks: &[Box<dyn Block>]) -> usize {
+    return blocks.iter()
+        .position(|block| !is_pause(block.as_ref()) && block.pulses(true).next().is_some())
+        .unwrap_or(blocks.len());
+}
+
+/// Lengthens the pilot tone of a standard or turbo speed data block to at least the given number of pulses.
+fn lengthen_pilot(block: &mut Box<dyn Block>, min_pilot_pulses: u16) {
+    if let Some(standard) = block.as_any().downcast_ref::<StandardSpeedDataBlock>() {
+        let header = standard.payload.data.first().is_some_and(|flag| *flag < 128);
+        let timings = TurboSpeedTimings::spectrum_standard(header);
+        if timings.length_tone_pilot < min_pilot_pulses {
+            let timings = TurboSpeedTimings { length_tone_pilot: min_pilot_pulses, ..timings };
+            *block = Box::new(TurboSpeedDataBlock::with_timings(timings, standard.pause, standard.payload.clone()));
+        }
+    } else if let Some(turbo) = block.as_any_mut().downcast_mut::<TurboSpeedDataBlock>() {
+        turbo.length_tone_pilot = turbo.length_tone_pilot.max(min_pilot_pulses);
+    }
+}
+
+/// Lengthens a non-zero pause, whether a pause block or the pause following a data block, to at least the given
+/// length.
+fn lengthen_pause(block: &mut dyn Block, min_gap_ms: u16) {
+    let block = block.as_any_mut();
+    let lengthen = |pause: u16| if pause > 0 { pause.max(min_gap_ms) } else { pause };
+
+    if let Some(block) = block.downcast_mut::<PauseOrStopTapeCommand>() {
+        block.set_pause(lengthen(block.pause()));
+    } else if let Some(block) = block.downcast_mut::<StandardSpeedDataBlock>() {
+        block.pause = lengthen(block.pause);
+    } else if let Some(block) = block.downcast_mut::<TurboSpeedDataBlock>() {
+        block.pause = lengthen(block.pause);
+    } else if let Some(block) = block.downcast_mut::<PureDataBlock>() {
+        block.set_pause(lengthen(block.pause()));
+    } else if let Some(block) = block.downcast_mut::<DirectRecording>() {
+        block.set_pause(lengthen(block.pause()));
+    } else if let Some(block) = block.downcast_mut::<GeneralizedDataBlock>() {
+        block.set_pause(lengthen(block.pause()));
+    } else if let Some(block) = block.downcast_mut::<KansasCityStandardDataBlock>() {
+        block.pause = lengthen(block.pause);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn block_durations(tzx_data: &TzxData, config: Config) -> Vec<Duration> {
+        return block_waveforms(&tzx_data.blocks, &Arc::new(config), 0)
+            .map(|waveforms| waveforms.iter().filter_map(|waveform| waveform.total_duration()).sum())
+            .collect();
+    }
+
+    #[test]
+    fn initial_silence_replaces_leading_pauses_without_renumbering_blocks() {
+        let tzx_data = TzxData::builder()
+            .pause(3000)
+            .text_description("Side A")
+            .pause(2000)
+            .pure_tone(1000, 100)
+            .pause(1000)
+            .build();
+
+        let durations = block_durations(&tzx_data, Config::builder().initial_silence_ms(500).build());
+        assert_eq!(durations.len(), tzx_data.blocks.len());
+        assert_eq!(durations[0], Duration::from_millis(500));
+        assert_eq!(durations[2], Duration::ZERO);
+        assert_eq!(durations[4], Duration::from_secs(1));
+
+        let durations = block_durations(&tzx_data, Config::builder().initial_silence_ms(0).build());
+        assert_eq!(&durations[..3], [Duration::ZERO; 3]);
+    }
+
+    #[test]
+    fn initial_silence_is_played_before_a_leading_signal() {
+        let tzx_data = TzxData::builder().pure_tone(1000, 100).build();
+        let tone = block_durations(&tzx_data, Config::builder().build())[0];
+        let durations = block_durations(&tzx_data, Config::builder().initial_silence_ms(500).build());
+        assert_eq!(durations, vec![tone + Duration::from_millis(500)]);
+    }
+}
@@ -8,24 +8,51 @@ use binrw::{
 };
 use std::io::{
     BufReader,
+    Cursor,
     ErrorKind,
     Read,
     Seek,
+    SeekFrom,
     Write,
 };
 
 use crate::tzx::{
     Header,
+    ParseDiagnostic,
+    ParseError,
+    ParseMode,
+    ParseReport,
     RecoveryEnum,
+    TapePulses,
+    TzxDataBuilder,
 };
 use crate::tzx::blocks::{
     read_block,
     write_block,
+    ArchiveInfo,
     Block,
     BlockRef,
     BlockType,
+    DamagedRegion,
+    GlueBlock,
+    GroupEnd,
+    GroupStart,
+    PauseOrStopTapeCommand,
 };
 
+/// Determines where [TzxData::split] divides data into pieces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SplitPoints {
+    /// After each 'stop tape' command, i.e. zero length [PauseOrStopTapeCommand].
+    StopTape,
+    /// Before each [GroupStart].
+    GroupStart,
+    /// Before each [GroupStart] and after each [GroupEnd], so that each group is in a piece of its own.
+    Groups,
+    /// Before each of the given zero-based block indices.
+    Blocks(Vec<usize>),
+}
+
 /// Represents a parsed TZX/CDT data source.
 #[derive(Clone, Debug, Default)]
 pub struct TzxData {
@@ -40,8 +67,20 @@ impl TzxData {
         TzxData { header: Header::default(), blocks: Vec::new() }
     }
 
+    /// Returns a [TzxDataBuilder] for constructing [TzxData] programmatically.
+    pub fn builder() -> TzxDataBuilder { TzxDataBuilder::new() }
+
     /// Attempts to parse [TzxData] from the supplied reader.
     ///
+    /// This is equivalent to [TzxData::parse] in [ParseMode::Lenient], discarding the [ParseReport]: blocks that fail
+    /// to parse are silently skipped.
+    pub fn read<R: Read + Seek>(reader: & mut R) -> Result<Self, Error> {
+        TzxData::read_le(reader)
+    }
+
+    /// Attempts to parse [TzxData] from the supplied reader, returning a [ParseReport] of any problems encountered
+    /// alongside the data.
+    ///
     /// The data is expected to start with a [Header]. After the header, we process the remainder of the data in a
     /// loop, reading a block type identification byte and then attempting to parse the corresponding block data using
     /// [read_block].
@@ -51,76 +90,223 @@ impl TzxData {
     /// the reader will become incorrectly aligned, resulting in further parse errors throughout the remainder of the
     /// file.
     ///
-    /// Any such parse errors will not cause a panic unless they cause the parser to try and do something bad like
-    /// reading beyond the end of the file. This will not happen with any validly formatted TZX/CDT files.
-    pub fn read<R: Read + Seek>(reader: & mut R) -> Result<Self, Error> {
-        TzxData::read_le(reader)
-    }
-
-    /// Writes [TzxData] to the supplied writer.
-    pub fn write<W: Write + Seek>(&self, writer: & mut W) -> Result<(), Error> {
-        self.write_le(writer)
-    }
-}
-
-impl BinRead for TzxData {
-    type Args<'a> = ();
-
-    fn read_options<R: Read + Seek>(
-        reader: &mut R,
-        _endian: binrw::Endian,
-        _args: Self::Args<'_>,
-    ) -> BinResult<Self> {
+    /// In [ParseMode::Lenient], blocks that fail to parse are recorded in the report and skipped. In
+    /// [ParseMode::Strict], the first such block causes parsing to fail with [ParseError::Block]. In
+    /// [ParseMode::Recover], the parser resynchronises on the next plausible block and retains
+    /// the bytes skipped over as a [DamagedRegion]. IO errors other than reaching the end of the data always cause
+    /// parsing to fail with [ParseError::Io].
+    pub fn parse<R: Read + Seek>(reader: &mut R, mode: ParseMode) -> Result<(Self, ParseReport), ParseError> {
         // Use a BufReader to handle underlying reads from the input.
         let mut reader = BufReader::new(reader);
 
-        let header = Header::read(&mut reader)?;
+        let header = Header::read(&mut reader).map_err(ParseError::Parse)?;
 
         let mut blocks: Vec<Box<dyn Block + 'static>> = Vec::new();
+        let mut report = ParseReport::new();
 
-        'parse_blocks: loop {
-            let block_type_result = RecoveryEnum::<BlockType, u8>::read_le(&mut reader);
-            if block_type_result.is_err() {
-                match block_type_result.unwrap_err() {
-                    Error::Io(why) => match why.kind() {
-                        ErrorKind::UnexpectedEof => break 'parse_blocks,
-                        _ => panic!("IO error: {}", why),
+        loop {
+            let offset = reader.stream_position()?;
+
+            let block_type_recoverable = match RecoveryEnum::<BlockType, u8>::read_le(&mut reader) {
+                Ok(block_type) => block_type,
+                Err(Error::Io(why)) if why.kind() == ErrorKind::UnexpectedEof => break,
+                Err(Error::Io(why)) => return Err(ParseError::Io(why)),
+                Err(why) => {
+                    let diagnostic = ParseDiagnostic {
+                        block_index: blocks.len(),
+                        offset,
+                        block_type: None,
+                        error: why,
+                        recovered: false,
+                    };
+                    if mode == ParseMode::Strict {
+                        return Err(ParseError::Block(diagnostic));
                     }
-                    other => eprintln!("Unhandled error: {}", other),
+                    report.push(diagnostic);
+                    reader.seek(SeekFrom::Start(offset + 1))?;
+                    continue;
                 }
-                if reader.seek_relative(1).is_ok() {
-                    continue 'parse_blocks;
-                } else {
-                    break 'parse_blocks;
-                };
-            }
-            let block_type_recoverable = block_type_result.unwrap();
+            };
 
-            let block_result: Result<Box<dyn Block>, Error> = read_block(block_type_recoverable, &mut reader);
-            let block = match block_result {
+            match read_block(block_type_recoverable, &mut reader) {
+                Ok(block) => blocks.push(block),
+                Err(Error::Io(why)) if why.kind() != ErrorKind::UnexpectedEof => return Err(ParseError::Io(why)),
                 Err(why) => {
-                    match block_type_recoverable {
-                        RecoveryEnum::Known(block_type) => {
-                            eprintln!("Failed to parse {} after block {}: {}", block_type, blocks.len(), why);
-                        }
-                        RecoveryEnum::Unknown(block_type_id) => {
-                            eprintln!("Failed to parse undefined block type {} after block {}: {}", block_type_id, blocks.len(), why);
-                        }
+                    let diagnostic = ParseDiagnostic {
+                        block_index: blocks.len(),
+                        offset,
+                        block_type: Some(block_type_recoverable),
+                        error: why,
+                        recovered: mode == ParseMode::Recover,
+                    };
+                    if mode == ParseMode::Strict {
+                        return Err(ParseError::Block(diagnostic));
+                    }
+                    report.push(diagnostic);
+
+                    if mode == ParseMode::Recover {
+                        let resume = resynchronise(&mut reader, offset + 1)?;
+                        let mut payload = vec![0; (resume - offset) as usize];
+                        reader.seek(SeekFrom::Start(offset))?;
+                        reader.read_exact(&mut payload)?;
+                        blocks.push(Box::new(DamagedRegion { offset, payload }));
                     }
-                    None
-                },
-                Ok(block) => Some(block),
+                }
+            }
+        }
+
+        return Ok((TzxData { header, blocks }, report));
+    }
+
+    /// Removes any [GlueBlock]s, as found where TZX files have been concatenated.
+    pub fn remove_glue_blocks(&mut self) {
+        self.blocks.retain(|block| !block.as_any().is::<GlueBlock>());
+    }
+
+    /// Splits the data into pieces at the given [SplitPoints], omitting any pieces that are empty or contain only
+    /// [ArchiveInfo].
+    ///
+    /// Each piece has the same header as the original data. Where the data contains an [ArchiveInfo] block, the first
+    /// such block is also included at the start of any piece that lacks one.
+    pub fn split(&self, split_points: &SplitPoints) -> Vec<TzxData> {
+        return self.split_indices(split_points).into_iter()
+            .map(|indices| TzxData {
+                header: self.header.clone(),
+                blocks: indices.iter().map(|index| self.blocks[*index].clone()).collect(),
+            })
+            .collect();
+    }
+
+    /// Returns the indices of the blocks of each of the pieces that [.split()](TzxData::split) would return, e.g. to
+    /// relate blocks of the pieces back to the original data.
+    pub fn split_indices(&self, split_points: &SplitPoints) -> Vec<Vec<usize>> {
+        let is_archive_info = |index: &usize| self.blocks[*index].as_any().is::<ArchiveInfo>();
+        let archive_info = (0..self.blocks.len()).find(is_archive_info);
+
+        let mut pieces: Vec<Vec<usize>> = vec![Vec::new()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            let split_before = match split_points {
+                SplitPoints::GroupStart | SplitPoints::Groups => block.as_any().is::<GroupStart>(),
+                SplitPoints::Blocks(indices) => indices.contains(&index),
+                SplitPoints::StopTape => false,
+            };
+            if split_before {
+                pieces.push(Vec::new());
+            }
+
+            pieces.last_mut().unwrap().push(index);
+
+            let split_after = match split_points {
+                SplitPoints::StopTape => block.as_any().downcast_ref::<PauseOrStopTapeCommand>().is_some_and(|b| b.is_stop_tape()),
+                SplitPoints::Groups => block.as_any().is::<GroupEnd>(),
+                _ => false,
             };
+            if split_after {
+                pieces.push(Vec::new());
+            }
+        }
+
+        return pieces.into_iter()
+            .filter(|indices| !indices.iter().all(is_archive_info))
+            .map(|mut indices| {
+                if let Some(archive_info) = archive_info
+                    && !indices.iter().any(is_archive_info) {
+                    indices.insert(0, archive_info);
+                }
+                indices
+            })
+            .collect();
+    }
+
+    /// Returns an iterator of the pulses of the whole tape, as lengths in ZX Spectrum t cycles and levels, with
+    /// `true` indicating a high pulse.
+    ///
+    /// Pulse lengths are exact, independent of any [Config](crate::tzx::Config) sample rate, and flow control blocks
+    /// are followed as described for [TapePulses]. Pauses are included as low pulses, with a length of 3500 t cycles
+    /// per millisecond.
+    ///
+    /// ```
+    /// use rtzx::TzxData;
+    ///
+    /// let tzx_data = TzxData::builder()
+    ///     .loop_start(3)
+    ///     .pulse_sequence(&[1000, 2000])
+    ///     .loop_end()
+    ///     .pause(1)
+    ///     .build();
+    ///
+    /// // The pause is combined with the last pulse of the sequence, since both are low.
+    /// let pulses: Vec<(u64, bool)> = tzx_data.pulses().collect();
+    /// assert_eq!(pulses, [(1000, true), (2000, false), (1000, true), (2000, false), (1000, true), (5500, false)]);
+    /// ```
+    pub fn pulses(&self) -> TapePulses { TapePulses::new(self.blocks.clone()) }
+
+    /// Writes [TzxData] to the supplied writer.
+    pub fn write<W: Write + Seek>(&self, writer: & mut W) -> Result<(), Error> {
+        self.write_le(writer)
+    }
+}
+
+/// The number of blocks that must parse successfully from a candidate offset for [resynchronise] to accept it, unless
+/// the end of the data is reached first.
+const RESYNCHRONISE_CONFIRM_BLOCKS: usize = 3;
+
+/// Scans forward from the given offset for the next offset from which a run of blocks of known types parse
+/// successfully, returning that offset or the end of the data if there is none. The reader is left positioned at the
+/// returned offset.
+///
+/// Requiring a run of blocks rejects most of the false positives that arise from short fixed length blocks (e.g.
+/// [PureTone](crate::tzx::blocks::PureTone)) matching arbitrary data, while blocks with length fields are only
+/// accepted where the length is consistent with the blocks that follow.
+///
+/// The remaining data is scanned in memory, since each candidate offset requires seeking back, which would discard the
+/// buffer of a [BufReader].
+fn resynchronise<R: Read + Seek>(reader: &mut R, start: u64) -> Result<u64, ParseError> {
+    reader.seek(SeekFrom::Start(start))?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let resume = match (0..data.len()).find(|candidate| blocks_follow(&data[*candidate..], RESYNCHRONISE_CONFIRM_BLOCKS)) {
+        Some(candidate) => start + candidate as u64,
+        None => start + data.len() as u64,
+    };
+    reader.seek(SeekFrom::Start(resume))?;
+
+    return Ok(resume);
+}
 
-            if block.is_none() { continue }
+/// Returns whether the given number of blocks of known types parse successfully from the start of the data, or fewer
+/// followed by the end of the data.
+fn blocks_follow(data: &[u8], count: usize) -> bool {
+    let mut reader = Cursor::new(data);
 
-            blocks.push(block.unwrap());
+    for _ in 0..count {
+        let block_type = match RecoveryEnum::<BlockType, u8>::read_le(&mut reader) {
+            Ok(RecoveryEnum::Known(BlockType::Undefined)) | Ok(RecoveryEnum::Unknown(_)) => return false,
+            Ok(block_type) => block_type,
+            Err(Error::Io(why)) if why.kind() == ErrorKind::UnexpectedEof => return true,
+            Err(_) => return false,
+        };
+
+        if read_block(block_type, &mut reader).is_err() {
+            return false;
         }
+    }
+
+    return true;
+}
+
+impl BinRead for TzxData {
+    type Args<'a> = ();
 
-        return Ok(TzxData {
-            header,
-            blocks
-        });
+    fn read_options<R: Read + Seek>(
+        reader: &mut R,
+        _endian: binrw::Endian,
+        _args: Self::Args<'_>,
+    ) -> BinResult<Self> {
+        TzxData::parse(reader, ParseMode::Lenient)
+            .map(|(tzx_data, _)| tzx_data)
+            .map_err(Into::into)
     }
 }
 
@@ -137,6 +323,12 @@ impl BinWrite for TzxData {
         self.header.write_le(writer)?;
 
         for block in self.blocks.iter() {
+            // Damaged regions retained by recovery parsing are written back out as they were read.
+            if let Some(damaged_region) = block.as_any().downcast_ref::<DamagedRegion>() {
+                damaged_region.payload.write_le(writer)?;
+                continue;
+            }
+
             if let Some(BlockRef::UndefinedBlockTypeBlock(b)) = block.as_block_ref() {
                 b.block_type.write_le(writer)?;
             } else if let Some(BlockRef::UnsupportedBlockTypeBlock(b)) = block.as_block_ref() {
@@ -151,3 +343,46 @@ impl BinWrite for TzxData {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tzx::blocks::PureTone;
+
+    fn tzx_data(blocks: Vec<Box<dyn Block>>) -> TzxData { TzxData { header: Header::default(), blocks } }
+
+    #[test]
+    fn blocks_after_a_group_are_split_from_it() {
+        let data = tzx_data(vec![
+            Box::new(PureTone::new(1000, 100)),
+            Box::new(GroupStart::new("Loader")),
+            Box::new(PureTone::new(1000, 100)),
+            Box::new(GroupEnd::new()),
+            Box::new(PureTone::new(500, 11)),
+            Box::new(GroupStart::new("Level 1")),
+            Box::new(GroupEnd::new()),
+        ]);
+        assert_eq!(data.split_indices(&SplitPoints::GroupStart), vec![vec![0], vec![1, 2, 3, 4], vec![5, 6]]);
+        assert_eq!(data.split_indices(&SplitPoints::Groups), vec![vec![0], vec![1, 2, 3], vec![4], vec![5, 6]]);
+    }
+
+    #[test]
+    fn recovered_damaged_regions_are_not_skipped() {
+        let tzx_data = TzxData::builder().pure_tone(1000, 100).pause(1000).build();
+        let mut bytes = Cursor::new(Vec::new());
+        tzx_data.write(&mut bytes).unwrap();
+        // Replace the pure tone's id with that of a text description, whose length then runs past the pause.
+        let mut bytes = bytes.into_inner();
+        let tone = bytes.iter().position(|byte| *byte == 0x12).unwrap();
+        bytes[tone] = 0x30;
+        bytes[tone + 1] = 0xff;
+
+        let (_, report) = TzxData::parse(&mut Cursor::new(&bytes), ParseMode::Lenient).unwrap();
+        assert!(report.has_skipped());
+
+        let (recovered, report) = TzxData::parse(&mut Cursor::new(&bytes), ParseMode::Recover).unwrap();
+        assert!(!report.is_clean() && !report.has_skipped());
+        assert!(report.diagnostics.iter().all(|diagnostic| diagnostic.outcome().starts_with("recovered")));
+        assert!(recovered.blocks[0].as_any().is::<DamagedRegion>());
+    }
+}
@@ -38,6 +38,10 @@ pub trait Waveform: Source + fmt::Display {
     /// Returns whether or not the waveform has started.
     fn started(&self) -> bool;
 
+    /// Returns whether the waveform is a pilot tone, which loaders use to synchronise before data. Default
+    /// implementation is to return `false`.
+    fn is_pilot(&self) -> bool { false }
+
     /// Returns the current baud where applicable. Default implementation is to return `None`,
     /// for waveforms that do not correspond to actual data such as pauses, tones, etc.
     fn current_baud(&self) -> Option<usize> { None }
@@ -61,3 +65,89 @@ impl Clone for Box<dyn Waveform + Send> {
         self.clone_box()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::tzx::{
+        Config,
+        blocks::{
+            Block,
+            DirectRecording,
+            GeneralizedDataBlock,
+            KansasCityStandardDataBlock,
+            PulseSequence,
+            TurboSpeedDataBlock,
+            TurboSpeedTimings,
+        },
+        data::DataPayload,
+    };
+
+    fn payload() -> DataPayload { DataPayload::new(5, Arc::new(vec![0xa7, 0x3c, 0xf1])) }
+
+    /// Checks that seeking to each sample plays the same samples as playing from the start and skipping to it, both
+    /// for a fresh waveform and for one that is repeatedly seeked forwards and backwards, as the player does.
+    fn assert_seeks_match_playback(waveform: &Box<dyn Waveform + Send>) {
+        let samples: Vec<f32> = waveform.clone().collect();
+        let sample_rate = waveform.sample_rate() as f64;
+        let position = |index: usize| Duration::from_secs_f64(index as f64 / sample_rate);
+
+        for index in 0..samples.len() + 3 {
+            let mut seeked = waveform.clone();
+            seeked.try_seek(position(index)).unwrap();
+            assert_eq!(seeked.collect::<Vec<_>>(), samples[index.min(samples.len())..], "{} seeking to {}", waveform, index);
+        }
+
+        let mut seeked = waveform.clone();
+        for index in (0..samples.len()).step_by(7).chain((0..samples.len()).rev().step_by(11)) {
+            seeked.try_seek(position(index)).unwrap();
+            let played: Vec<f32> = seeked.by_ref().take(20).collect();
+            assert_eq!(played, samples[index..(index + 20).min(samples.len())], "{} seeking to {}", waveform, index);
+        }
+    }
+
+    fn assert_block_seeks_match_playback(block: &dyn Block, count_waveforms: usize) {
+        let config = Arc::new(Config::builder().build());
+        for start_pulse_high in [false, true] {
+            let waveforms = block.get_waveforms(config.clone(), start_pulse_high);
+            for waveform in &waveforms[..count_waveforms] {
+                assert_seeks_match_playback(waveform);
+            }
+        }
+    }
+
+    #[test]
+    fn seeking_matches_playback() {
+        let timings = TurboSpeedTimings {
+            length_pulse_pilot: 2168,
+            length_pulse_sync_first: 667,
+            length_pulse_sync_second: 735,
+            length_pulse_zero: 855,
+            length_pulse_one: 1710,
+            length_tone_pilot: 21,
+        };
+        let turbo = TurboSpeedDataBlock::with_timings(timings, 0, payload());
+        assert_block_seeks_match_playback(&turbo, 3);
+        assert_block_seeks_match_playback(&GeneralizedDataBlock::from(&turbo), 2);
+
+        let kcs = KansasCityStandardDataBlock { length_tone_pilot: 9, payload: payload(), ..KansasCityStandardDataBlock::new() };
+        assert_block_seeks_match_playback(&kcs, 2);
+
+        assert_block_seeks_match_playback(&PulseSequence::new(&[2168, 667, 735, 855, 1710]), 1);
+        assert_block_seeks_match_playback(&DirectRecording::new(200, 0, payload()), 1);
+    }
+
+    #[test]
+    fn pilot_tones_are_marked_as_pilots() {
+        let config = Arc::new(Config::builder().build());
+        let turbo = TurboSpeedDataBlock::with_timings(TurboSpeedTimings::default(), 1, payload());
+        for waveforms in [turbo.get_waveforms(config.clone(), true), GeneralizedDataBlock::from(&turbo).get_waveforms(config, true)] {
+            let pilots: Vec<bool> = waveforms.iter().map(|waveform| waveform.is_pilot()).collect();
+            assert!(pilots[0] && waveforms[0].clone().is_pilot());
+            assert!(!pilots[1..].contains(&true));
+        }
+    }
+}
@@ -12,7 +12,7 @@ use std::time::Duration;
 use crate::tzx::{
     Config,
     data::{DataPayload, DataPayloadWithPosition},
-    waveforms::{Pulse, Waveform},
+    waveforms::{Pulse, Waveform, pulse::SampleClock},
 };
 
 /// Iterates pulses for use by a [DataWaveform].
@@ -57,18 +57,6 @@ impl Iterator for DataPulseIterator {
 
 impl DataPulseIterator {
     fn len(&self) -> usize { self.payload.total_bits() * 2 }
-
-    fn prev(&mut self) -> Option<Pulse> {
-        if self.current_pulse_index > 0 {
-            self.current_pulse_index -= 1;
-            let current_bit = self.payload.data.view_bits::<Msb0>()[self.current_pulse_index as usize / 2];
-            self.current_pulse.length = if current_bit { self.length_pulse_one } else { self.length_pulse_zero };
-            self.current_pulse.high = !((self.current_pulse_index % 2 == 0) ^ self.start_pulse_high);
-            Some(self.current_pulse.clone())
-        } else {
-            None
-        }
-    }
 }
 
 /// A waveform for standard data encoding as used by [StandardSpeedDataBlock](crate::tzx::blocks::StandardSpeedDataBlock),
@@ -86,7 +74,10 @@ pub struct DataWaveform {
     payload: DataPayload,
     pulse_iterator: DataPulseIterator,
     current_pulse: Pulse,
+    current_pulse_len: u32,
     current_pulse_sample_index: usize,
+    clock: SampleClock,
+    start_t_cycles: u64,
 }
 
 impl DataWaveform {
@@ -94,6 +85,8 @@ impl DataWaveform {
     pub fn new(config: Arc<Config>, length_pulse_zero: u16, length_pulse_one: u16, payload: DataPayload, start_pulse_high: bool) -> Self {
         let mut pulse_iterator = DataPulseIterator::new(config.clone(), length_pulse_zero, length_pulse_one, payload.clone(), start_pulse_high);
         let current_pulse = pulse_iterator.next().unwrap_or(Pulse::new(config.clone(), 0, false));
+        let mut clock = SampleClock::new();
+        let current_pulse_len = clock.samples(&current_pulse);
 
         return Self {
             config,
@@ -102,9 +95,21 @@ impl DataWaveform {
             payload,
             pulse_iterator,
             current_pulse,
+            current_pulse_len,
             current_pulse_sample_index: 0,
+            clock,
+            start_t_cycles: 0,
         }
     }
+
+    /// Continues on from the given number of t cycles of preceding pulses in the block, carrying the rounding of
+    /// pulses to whole samples over from them (see [SampleClock]).
+    pub fn following(mut self, t_cycles: u64) -> Self {
+        self.start_t_cycles = t_cycles;
+        self.clock = SampleClock::following(t_cycles);
+        self.current_pulse_len = self.clock.samples(&self.current_pulse);
+        return self;
+    }
 }
 
 impl fmt::Display for DataWaveform {
@@ -127,14 +132,14 @@ impl Iterator for DataWaveform {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pulse_sample: Option<Self::Item> = self.current_pulse.next_sample(self.current_pulse_sample_index as u32);
-        if pulse_sample.is_some() {
+        if (self.current_pulse_sample_index as u32) < self.current_pulse_len {
             self.current_pulse_sample_index += 1;
-            return pulse_sample;
+            return Some(self.current_pulse.sample());
         }
         let next_pulse = self.pulse_iterator.next();
         if next_pulse.is_some() {
             self.current_pulse = next_pulse.unwrap();
+            self.current_pulse_len = self.clock.samples(&self.current_pulse);
             self.current_pulse_sample_index = 0;
             return self.next();
         }
@@ -148,50 +153,32 @@ impl Source for DataWaveform {
     fn current_span_len(&self) -> Option<usize> { None }
 
     fn total_duration(&self) -> Option<Duration> {
-        let mut duration = Duration::ZERO;
-        let mut pulse = self.current_pulse.clone();
         let bit_counts = self.payload.bit_counts();
-        pulse.length = self.length_pulse_one;
-        duration += pulse.duration() * bit_counts.ones as u32 * 2;
-        pulse.length = self.length_pulse_zero;
-        duration += pulse.duration() * bit_counts.zeros as u32 * 2;
-        return Some(duration);
+        let t_cycles = (self.length_pulse_one as u64 * bit_counts.ones as u64 + self.length_pulse_zero as u64 * bit_counts.zeros as u64) * 2;
+        return Some(self.config.t_cycles_duration(self.start_t_cycles, t_cycles));
     }
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         if self.payload.len() == 0 { return Ok(()) }
-        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as usize;
-
-        // Use the pos / total_duration percentage time progress
-        // to estimate the byte position within the payload.
-        let estimated_byte_index = std::cmp::min((self.payload.len() as f32 * pos.div_duration_f32(self.total_duration().unwrap())) as usize, self.payload.len() - 1);
-
-        // Calculate the number of samples in the start to estimated byte position range.
-        let mut pulse = self.current_pulse.clone();
-        let mut pulse_samples: usize = 0;
-        let bit_counts_to_index = self.payload.bit_counts_for_range(0..estimated_byte_index).unwrap();
-        pulse.length = self.length_pulse_one;
-        pulse_samples += pulse.len() as usize * bit_counts_to_index.ones * 2;
-        pulse.length = self.length_pulse_zero;
-        pulse_samples += pulse.len() as usize * bit_counts_to_index.zeros * 2;
-
-        self.pulse_iterator.current_pulse_index = estimated_byte_index * 16;
-        self.current_pulse_sample_index = 0;
-
-        while pulse_samples > samples && let Some(prev_pulse) = self.pulse_iterator.prev() {
-            self.current_pulse = prev_pulse;
-            pulse_samples -= self.current_pulse.len() as usize;
-        }
-
-        while pulse_samples < samples {
-            pulse_samples += self.current_pulse.len() as usize;
-            if pulse_samples > samples {
-                self.current_pulse_sample_index = pulse_samples - samples;
-            } else {
-                if let Some(next_pulse) = self.pulse_iterator.next() {
-                    self.current_pulse = next_pulse;
-                }
-            }
+        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u64;
+
+        // Find the byte played at the given position from the bit counts of the preceding bytes, then walk its pulses.
+        let t_cycles_to_byte = |byte_index: usize| {
+            let bit_counts = self.payload.bit_counts_for_range(0..byte_index).unwrap();
+            (self.length_pulse_one as u64 * bit_counts.ones as u64 + self.length_pulse_zero as u64 * bit_counts.zeros as u64) * 2
+        };
+        let byte_index = SampleClock::seek_index(&self.config, self.start_t_cycles, samples, self.payload.len(), t_cycles_to_byte);
+        let t_cycles = t_cycles_to_byte(byte_index);
+
+        self.pulse_iterator.current_pulse_index = byte_index * 16;
+        match SampleClock::seek(&self.config, self.start_t_cycles, t_cycles, samples, &mut self.pulse_iterator) {
+            Some((pulse, t_cycles, sample_index)) => {
+                self.clock = SampleClock::following(self.start_t_cycles + t_cycles);
+                self.current_pulse_len = self.clock.samples(&pulse);
+                self.current_pulse = pulse;
+                self.current_pulse_sample_index = sample_index as usize;
+            },
+            None => self.current_pulse_sample_index = self.current_pulse_len as usize,
         }
         return Ok(());
     }
@@ -12,7 +12,7 @@ use std::time::Duration;
 use crate::tzx::{
     Config,
     data::DataPayload,
-    waveforms::{Pulse,Waveform}
+    waveforms::{Pulse, Waveform, pulse::SampleClock},
 };
 
 /// A waveform for direct recording encoding as used by [DirectRecording](crate::tzx::blocks::DirectRecording).
@@ -29,13 +29,17 @@ pub struct DirectWaveform {
     payload: DataPayload,
     current_pulse: Pulse,
     current_pulse_index: usize,
+    current_pulse_len: u32,
     current_pulse_sample_index: usize,
+    clock: SampleClock,
 }
 
 impl DirectWaveform {
     pub fn new(config: Arc<Config>, length_sample: u16, payload: DataPayload) -> Self {
         let first_bit = payload.data.view_bits::<Msb0>()[0];
         let current_pulse = Pulse::new(config.clone(), length_sample, first_bit);
+        let mut clock = SampleClock::new();
+        let current_pulse_len = clock.samples(&current_pulse);
 
         return Self {
             config,
@@ -43,7 +47,9 @@ impl DirectWaveform {
             payload,
             current_pulse,
             current_pulse_index: 0,
+            current_pulse_len,
             current_pulse_sample_index: 0,
+            clock,
         }
     }
 
@@ -51,11 +57,25 @@ impl DirectWaveform {
         self.payload.data.view_bits::<Msb0>()[self.payload.total_bits() - 1]
     }
 
+    /// Returns the physical pulses encoded by the payload as lengths in ZX Spectrum t cycles and levels, i.e. with
+    /// each run of identical bits combined into a single pulse.
+    pub fn pulse_lengths(&self) -> Vec<(u32, bool)> {
+        let mut pulses: Vec<(u32, bool)> = Vec::new();
+        for bit in self.payload.data.view_bits::<Msb0>()[..self.payload.total_bits()].iter() {
+            match pulses.last_mut() {
+                Some((length, high)) if *high == *bit => *length += self.length_sample as u32,
+                _ => pulses.push((self.length_sample as u32, *bit)),
+            }
+        }
+        return pulses;
+    }
+
     fn has_data_remaining(&self) -> bool { self.current_pulse_index < self.payload.total_bits() }
 
     fn update_pulse(&mut self) {
         if self.has_data_remaining() {
             self.current_pulse.high = self.payload.data.view_bits::<Msb0>()[self.current_pulse_index];
+            self.current_pulse_len = self.clock.samples(&self.current_pulse);
         }
     }
 }
@@ -65,10 +85,9 @@ impl Iterator for DirectWaveform {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.has_data_remaining() {
-            let pulse_sample: Option<Self::Item> = self.current_pulse.next_sample(self.current_pulse_sample_index as u32);
-            if pulse_sample.is_some() {
+            if (self.current_pulse_sample_index as u32) < self.current_pulse_len {
                 self.current_pulse_sample_index += 1;
-                return pulse_sample;
+                return Some(self.current_pulse.sample());
             }
 
             self.current_pulse_index += 1;
@@ -87,30 +106,22 @@ impl Source for DirectWaveform {
     fn sample_rate(&self) -> SampleRate { self.config.sample_rate }
     fn current_span_len(&self) -> Option<usize> { None }
 
-    fn total_duration(&self) -> Option<Duration> { Some(self.current_pulse.duration() * self.payload.total_bits() as u32) }
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.config.t_cycles_duration(0, self.length_sample as u64 * self.payload.total_bits() as u64))
+    }
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         if self.payload.len() == 0 { return Ok(()) }
-        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as usize;
-
-        let byte_index = ((self.payload.len() - 1) as f32 * pos.div_duration_f32(self.total_duration().unwrap())) as usize;
-
-        // Calculate the number of samples in the start to estimated byte position range.
-        let mut pulse_samples: usize = 0;
-        let bit_counts_to_index = self.payload.bit_counts_for_range(0..byte_index).unwrap();
-        pulse_samples += self.current_pulse.len() as usize * bit_counts_to_index.total;
-
-        self.current_pulse_index = byte_index * 8;
-        self.current_pulse_sample_index = 0;
-        while pulse_samples < samples && self.has_data_remaining() {
-            pulse_samples += self.current_pulse.len() as usize;
-            if pulse_samples > samples {
-                self.current_pulse_sample_index = pulse_samples - samples;
-            } else {
-                self.current_pulse_index += 1;
-                self.update_pulse();
-            }
-        }
+        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u64;
+
+        // Each bit is played as a pulse of the same length, so the bit played at the given position can be found
+        // directly. Seeking past the end leaves the last bit fully played.
+        let length_sample = self.length_sample as u64;
+        self.current_pulse_index = SampleClock::seek_index(&self.config, 0, samples, self.payload.total_bits(), |index| index as u64 * length_sample);
+        let t_cycles = self.current_pulse_index as u64 * length_sample;
+        self.clock = SampleClock::following(t_cycles);
+        self.update_pulse();
+        self.current_pulse_sample_index = std::cmp::min(samples - SampleClock::offset(&self.config, 0, t_cycles), self.current_pulse_len as u64) as usize;
         return Ok(());
     }
 }
@@ -12,9 +12,9 @@ use std::time::Duration;
 
 use crate::tzx::{
     Config,
-    blocks::generalized_data_block::SymbolDefinition,
+    blocks::{GeneralizedDataBlock, generalized_data_block::SymbolDefinition},
     data::DataPayload,
-    waveforms::{Pulse, Waveform},
+    waveforms::{Pulse, Waveform, pulse::SampleClock},
 };
 
 // Iterates pulses for use by a [GeneralizedWaveform].
@@ -30,16 +30,20 @@ pub struct GeneralizedPulseIterator {
 }
 
 impl GeneralizedPulseIterator {
+    /// Creates a pulse iterator. The level preceding the first pulse is taken to be the opposite of
+    /// `start_pulse_high`, with the polarity of the first symbol applied to it.
     pub fn new(config: Arc<Config>, symbols: Arc<Vec<SymbolDefinition>>, payload: DataPayload, start_pulse_high: bool) -> Self {
-        let range= 0..symbols.len().ilog2() as usize;
+        let range= 0..GeneralizedDataBlock::symbol_key_bits(symbols.len());
         let current_symbol_key = payload.data.view_bits::<Msb0>()[range].load_be::<u8>();
+        let first_symbol = &symbols[current_symbol_key as usize];
+        let first_pulse_high = first_symbol.polarity.next_polarity(!start_pulse_high);
 
         return Self {
             symbols: symbols.clone(),
             payload,
             current_payload_bit_index: 0,
             current_symbol_key,
-            current_pulse: Pulse::new(config, symbols[current_symbol_key as usize].pulses[0], start_pulse_high),
+            current_pulse: Pulse::new(config, first_symbol.pulses[0], first_pulse_high),
             current_pulse_index: 0,
             started: false,
          }
@@ -47,7 +51,7 @@ impl GeneralizedPulseIterator {
 
     fn current_symbol(&self) -> &SymbolDefinition { &self.symbols[self.current_symbol_key as usize] }
 
-    fn symbol_key_size(&self) -> usize { self.symbols.len().ilog2() as usize }
+    fn symbol_key_size(&self) -> usize { GeneralizedDataBlock::symbol_key_bits(self.symbols.len()) }
 
     fn next_symbol_key(&mut self) -> Option<u8> {
         self.current_payload_bit_index += self.symbol_key_size();
@@ -69,18 +73,6 @@ impl GeneralizedPulseIterator {
             !self.current_pulse.high
         }
     }
-
-    fn seek_and_fetch(&mut self, bit_index: usize) -> Option<Pulse> {
-        self.current_payload_bit_index = bit_index - (bit_index % self.symbol_key_size());
-        if self.has_data_remaining() {
-            let range= self.current_payload_bit_index..(self.current_payload_bit_index + self.symbol_key_size());
-            self.current_symbol_key = self.payload.data.view_bits::<Msb0>()[range].load_be::<u8>();
-            self.update_pulse();
-            return Some(self.current_pulse.clone());
-        } else {
-            None
-        }
-    }
 }
 
 impl Iterator for GeneralizedPulseIterator {
@@ -130,21 +122,26 @@ pub struct GeneralizedWaveform {
     last_pulse_high: bool,
     pulse_iterator: GeneralizedPulseIterator,
     current_pulse: Pulse,
+    current_pulse_len: u32,
     current_pulse_sample_index: usize,
-    cached_seek_data: (Duration, usize, usize),
+    clock: SampleClock,
+    start_t_cycles: u64,
+    is_pilot: bool,
     cached_symbol_pulse_lengths: OnceLock<HashMap<u16,u16>>,
     cached_total_duration: Duration,
     cached_total_pulses: usize,
+    cached_total_t_cycles: u64,
 }
 
 impl Clone for GeneralizedWaveform {
     fn clone(&self) -> Self {
-        GeneralizedWaveform::new(
+        let waveform = GeneralizedWaveform::new(
             self.config.clone(),
             self.symbols.clone(),
             self.payload.clone(),
             self.start_pulse_high.clone(),
-        )
+        ).following(self.start_t_cycles);
+        return GeneralizedWaveform { is_pilot: self.is_pilot, ..waveform };
     }
 }
 
@@ -154,17 +151,20 @@ impl GeneralizedWaveform {
         let mut pulse_iterator = GeneralizedPulseIterator::new(config.clone(), symbols.clone(), payload.clone(), start_pulse_high);
         let pulse_iterator_for_totals = pulse_iterator.clone();
 
-        let mut total_duration = Duration::ZERO;
+        let mut total_t_cycles: u64 = 0;
         let mut total_pulses = 0;
         let mut last_pulse_high = false;
 
         for pulse in pulse_iterator_for_totals {
-            total_duration += pulse.duration();
+            total_t_cycles += pulse.length as u64;
             total_pulses += 1;
             last_pulse_high = pulse.high;
         }
 
         let current_pulse = pulse_iterator.next().unwrap_or(Pulse::new(config.clone(), 0, false));
+        let mut clock = SampleClock::new();
+        let current_pulse_len = clock.samples(&current_pulse);
+        let total_duration = config.t_cycles_duration(0, total_t_cycles);
 
         return Self {
             config,
@@ -174,14 +174,38 @@ impl GeneralizedWaveform {
             last_pulse_high,
             pulse_iterator,
             current_pulse,
+            current_pulse_len,
             current_pulse_sample_index: 0,
-            cached_seek_data: (Duration::ZERO, 0, 0),
+            clock,
+            start_t_cycles: 0,
+            is_pilot: false,
             cached_symbol_pulse_lengths: OnceLock::new(),
             cached_total_duration: total_duration,
             cached_total_pulses: total_pulses,
+            cached_total_t_cycles: total_t_cycles,
         };
     }
 
+    /// Continues on from the given number of t cycles of preceding pulses in the block, carrying the rounding of
+    /// pulses to whole samples over from them (see [SampleClock]).
+    pub fn following(mut self, t_cycles: u64) -> Self {
+        self.start_t_cycles = t_cycles;
+        self.clock = SampleClock::following(t_cycles);
+        self.current_pulse_len = self.clock.samples(&self.current_pulse);
+        self.cached_total_duration = self.config.t_cycles_duration(t_cycles, self.cached_total_t_cycles);
+        return self;
+    }
+
+    /// Marks the waveform as the pilot tone of its block, i.e. the pilot and sync pulses of a generalized data
+    /// block, so that [Player::seek_pilot](crate::tzx::Player::seek_pilot) can seek to it.
+    pub fn pilot(mut self) -> Self {
+        self.is_pilot = true;
+        return self;
+    }
+
+    /// Returns the total length of the waveform's pulses in ZX Spectrum t cycles.
+    pub fn t_cycles(&self) -> u64 { self.cached_total_t_cycles }
+
     fn compute_symbol_pulse_lengths(&self) -> HashMap<u16, u16> {
         let mut symbol_pulse_lengths = HashMap::new();
         let mut shortest: u16 = 65535;
@@ -223,14 +247,14 @@ impl Iterator for GeneralizedWaveform {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pulse_sample: Option<Self::Item> = self.current_pulse.next_sample(self.current_pulse_sample_index as u32);
-        if pulse_sample.is_some() {
+        if (self.current_pulse_sample_index as u32) < self.current_pulse_len {
             self.current_pulse_sample_index += 1;
-            return pulse_sample;
+            return Some(self.current_pulse.sample());
         }
         let next_pulse = self.pulse_iterator.next();
         if next_pulse.is_some() {
             self.current_pulse = next_pulse.unwrap();
+            self.current_pulse_len = self.clock.samples(&self.current_pulse);
             self.current_pulse_sample_index = 0;
             return self.next();
         }
@@ -247,30 +271,26 @@ impl Source for GeneralizedWaveform {
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         if self.payload.len() == 0 { return Ok(()) }
-        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as usize;
-
-        // Use cached seek data to speed up seek if we're seeking forwards.
-        let (cached_pos, mut pulse_samples, mut payload_bit_index) = self.cached_seek_data;
-        if pos < cached_pos {
-            pulse_samples = 0;
-            payload_bit_index = 0;
-        }
-
-        self.pulse_iterator.current_payload_bit_index = payload_bit_index;
-        self.current_pulse_sample_index = 0;
-        self.current_pulse = self.pulse_iterator.seek_and_fetch(payload_bit_index).unwrap();
+        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u64;
+
+        // Symbols vary in length, so pulses are walked to the one played at the given position. The display seeks on
+        // every frame to follow playback, so continue on from the current pulse when seeking forwards.
+        let t_cycles = self.clock.t_cycles() - self.start_t_cycles - self.current_pulse.length as u64;
+        let found = if SampleClock::offset(&self.config, self.start_t_cycles, t_cycles) <= samples {
+            let pulses = std::iter::once(self.current_pulse.clone()).chain(&mut self.pulse_iterator);
+            SampleClock::seek(&self.config, self.start_t_cycles, t_cycles, samples, pulses)
+        } else {
+            self.pulse_iterator = GeneralizedPulseIterator::new(self.config.clone(), self.symbols.clone(), self.payload.clone(), self.start_pulse_high);
+            SampleClock::seek(&self.config, self.start_t_cycles, 0, samples, &mut self.pulse_iterator)
+        };
 
-        while pulse_samples < samples && self.pulse_iterator.has_data_remaining() {
-            pulse_samples += self.current_pulse.len() as usize;
-            if pulse_samples > samples {
-                self.current_pulse_sample_index = pulse_samples - samples;
-            } else {
-                self.current_pulse = self.pulse_iterator.next().unwrap();
-            }
+        if let Some((pulse, t_cycles, sample_index)) = found {
+            self.clock = SampleClock::following(self.start_t_cycles + t_cycles);
+            self.current_pulse_len = self.clock.samples(&pulse);
+            self.current_pulse = pulse;
+            self.current_pulse_sample_index = sample_index as usize;
         }
 
-        self.cached_seek_data = (pos, pulse_samples, self.pulse_iterator.current_payload_bit_index);
-
         return Ok(());
     }
 }
@@ -282,6 +302,7 @@ impl Waveform for GeneralizedWaveform {
 
     fn started(&self) -> bool { self.pulse_iterator.current_pulse_index > 0 || self.current_pulse_sample_index > 0 }
 
+    fn is_pilot(&self) -> bool { self.is_pilot }
 
     fn current_baud(&self) -> Option<usize> {
         let target_duration = Duration::from_millis(100);
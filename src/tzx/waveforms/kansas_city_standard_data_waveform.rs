@@ -14,7 +14,7 @@ use crate::tzx::{
     Config,
     blocks::kansas_city_standard_data_block::KCSBitByteConfig,
     data::{DataPayload, DataPayloadWithPosition},
-    waveforms::{Pulse, Waveform}
+    waveforms::{Pulse, Waveform, pulse::SampleClock},
 };
 
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
@@ -213,7 +213,10 @@ pub struct KansasCityStandardDataWaveform {
     payload: DataPayload,
     pulse_iterator: KCSDataPulseIterator,
     current_pulse: Pulse,
+    current_pulse_len: u32,
     current_pulse_sample_index: usize,
+    clock: SampleClock,
+    start_t_cycles: u64,
     cached_symbol_pulse_lengths: OnceLock<HashMap<u16,u16>>,
 }
 
@@ -236,6 +239,8 @@ impl KansasCityStandardDataWaveform {
             start_pulse_high
         );
         let current_pulse = pulse_iterator.next().unwrap_or(Pulse::new(config.clone(), 0, false));
+        let mut clock = SampleClock::new();
+        let current_pulse_len = clock.samples(&current_pulse);
 
         return Self {
             config,
@@ -244,11 +249,23 @@ impl KansasCityStandardDataWaveform {
             payload,
             pulse_iterator,
             current_pulse,
+            current_pulse_len,
             current_pulse_sample_index: 0,
+            clock,
+            start_t_cycles: 0,
             cached_symbol_pulse_lengths: OnceLock::new(),
         }
     }
 
+    /// Continues on from the given number of t cycles of preceding pulses in the block, carrying the rounding of
+    /// pulses to whole samples over from them (see [SampleClock]).
+    pub fn following(mut self, t_cycles: u64) -> Self {
+        self.start_t_cycles = t_cycles;
+        self.clock = SampleClock::following(t_cycles);
+        self.current_pulse_len = self.clock.samples(&self.current_pulse);
+        return self;
+    }
+
     fn compute_symbol_pulse_lengths(&self) -> HashMap<u16, u16> {
         let mut symbol_pulse_lengths = HashMap::new();
         let mut shortest: u16 = self.length_pulse_one;
@@ -267,12 +284,10 @@ impl KansasCityStandardDataWaveform {
         return symbol_pulse_lengths;
     }
 
-    fn samples_duration_to_byte_index(&self, byte_index: usize) -> (usize, Duration) {
+    /// Returns the number of t cycles of the pulses preceding the byte at the given index.
+    fn t_cycles_to_byte_index(&self, byte_index: usize) -> u64 {
         let mut count_ones: usize;
         let mut count_zeros: usize;
-        let mut samples: usize = 0;
-        let mut duration = Duration::ZERO;
-        let mut pulse = self.current_pulse.clone();
         let bit_counts = match byte_index >= self.payload.len() {
             true => *self.payload.bit_counts(),
             false => self.payload.bit_counts_for_range(0..byte_index).unwrap(),
@@ -297,15 +312,7 @@ impl KansasCityStandardDataWaveform {
         let count_pulses_ones = count_ones * self.pulse_iterator.bit_byte_config.count_pulses_one() as usize;
         let count_pulses_zeros = count_zeros * self.pulse_iterator.bit_byte_config.count_pulses_zero() as usize;
 
-        pulse.length = self.length_pulse_one;
-        samples += pulse.len() as usize * count_pulses_ones as usize;
-        duration += pulse.duration() * count_pulses_ones as u32;
-
-        pulse.length = self.length_pulse_zero;
-        samples += pulse.len() as usize * count_pulses_zeros as usize;
-        duration += pulse.duration() * count_pulses_zeros as u32;
-
-        return (samples, duration);
+        return self.length_pulse_one as u64 * count_pulses_ones as u64 + self.length_pulse_zero as u64 * count_pulses_zeros as u64;
     }
 }
 
@@ -329,14 +336,14 @@ impl Iterator for KansasCityStandardDataWaveform {
     type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pulse_sample: Option<Self::Item> = self.current_pulse.next_sample(self.current_pulse_sample_index as u32);
-        if pulse_sample.is_some() {
+        if (self.current_pulse_sample_index as u32) < self.current_pulse_len {
             self.current_pulse_sample_index += 1;
-            return pulse_sample;
+            return Some(self.current_pulse.sample());
         }
         let next_pulse = self.pulse_iterator.next();
         if next_pulse.is_some() {
             self.current_pulse = next_pulse.unwrap();
+            self.current_pulse_len = self.clock.samples(&self.current_pulse);
             self.current_pulse_sample_index = 0;
             return self.next();
         }
@@ -350,44 +357,31 @@ impl Source for KansasCityStandardDataWaveform {
     fn current_span_len(&self) -> Option<usize> { None }
 
     fn total_duration(&self) -> Option<Duration> {
-        Some(self.samples_duration_to_byte_index(self.payload.len()).1)
+        Some(self.config.t_cycles_duration(self.start_t_cycles, self.t_cycles_to_byte_index(self.payload.len())))
     }
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         if self.payload.len() == 0 { return Ok(()) }
-        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as usize;
-
-        // Use the pos / total_duration percentage time progress
-        // to estimate the byte position within the payload.
-        let estimated_byte_index = std::cmp::min((self.payload.len() as f32 * pos.div_duration_f32(self.total_duration().unwrap())) as usize, self.payload.len() - 1);
-        let estimated_byte_index = if estimated_byte_index > 0 { estimated_byte_index - 1 } else { 0 };
+        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u64;
 
-        // Calculate number of samples to estimated_byte_index
-        let (mut seek_samples, _) = self.samples_duration_to_byte_index(estimated_byte_index);
-        // eprintln!("{:?}/{:?} : {:?}/{:?}", estimated_byte_index, self.payload.len(), seek_samples, samples);
+        // Find the byte played at the given position, then reset the pulse iterator to the start of that byte and
+        // walk its pulses.
+        let byte_index = SampleClock::seek_index(&self.config, self.start_t_cycles, samples, self.payload.len(), |byte_index| self.t_cycles_to_byte_index(byte_index));
+        let t_cycles = self.t_cycles_to_byte_index(byte_index);
 
-        // Set pulse iterator to the estimated byte_index and reset to start of byte.
-        self.pulse_iterator.payload_byte_index = estimated_byte_index;
+        self.pulse_iterator.payload_byte_index = byte_index;
         self.pulse_iterator.bit_index = 0;
         self.pulse_iterator.bit_pulse_index = 0;
         self.pulse_iterator.bit_state = KCSBitState::Start;
 
-        self.current_pulse_sample_index = 0;
-
-        // while pulse_samples > samples && let Some(prev_pulse) = self.pulse_iterator.prev() {
-        //     self.current_pulse = prev_pulse;
-        //     pulse_samples -= self.current_pulse.len() as usize;
-        // }
-
-        while seek_samples < samples {
-            seek_samples += self.current_pulse.len() as usize;
-            if seek_samples > samples {
-                self.current_pulse_sample_index = seek_samples - samples;
-            } else {
-                if let Some(next_pulse) = self.pulse_iterator.next() {
-                    self.current_pulse = next_pulse;
-                }
-            }
+        match SampleClock::seek(&self.config, self.start_t_cycles, t_cycles, samples, &mut self.pulse_iterator) {
+            Some((pulse, t_cycles, sample_index)) => {
+                self.clock = SampleClock::following(self.start_t_cycles + t_cycles);
+                self.current_pulse_len = self.clock.samples(&pulse);
+                self.current_pulse = pulse;
+                self.current_pulse_sample_index = sample_index as usize;
+            },
+            None => self.current_pulse_sample_index = self.current_pulse_len as usize,
         }
         return Ok(());
     }
@@ -28,9 +28,9 @@ pub enum PauseType {
 }
 
 impl PauseType {
-    fn sample(&self) -> f32 {
+    fn sample(&self, config: &Config) -> f32 {
         match self {
-            PauseType::Zero => 0f32,
+            PauseType::Zero => config.pause_level,
             PauseType::Low | PauseType::StartLow => -1.0f32,
             PauseType::High | PauseType::StartHigh => -1.0f32,
         }
@@ -86,7 +86,7 @@ impl Iterator for PauseWaveform {
                 return Some(0.0f32);
             } else if self.sample_index < (self.config.sample_rate as f64 / 1000.0).round() as u64 {
                 self.sample_index += 1;
-                return Some(self.pause_type.sample());
+                return Some(self.pause_type.sample(&self.config));
             }
 
             self.start_pause_pulse = false;
@@ -94,7 +94,7 @@ impl Iterator for PauseWaveform {
 
         if self.sample_index < self.len() {
             self.sample_index += 1;
-            return Some(self.pause_type.sample());
+            return Some(self.pause_type.sample(&self.config));
         }
         return None;
     }
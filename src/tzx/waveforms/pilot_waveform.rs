@@ -10,7 +10,7 @@ use std::time::Duration;
 
 use crate::tzx::{
     Config,
-    waveforms::{Pulse, Waveform},
+    waveforms::{Pulse, Waveform, pulse::SampleClock},
 };
 
 
@@ -25,27 +25,36 @@ pub struct PilotWaveform {
     config: Arc<Config>,
     length_pulse: u16,
     length_tone: u16,
+    start_pulse_high: bool,
     current_pulse: Pulse,
     current_pulse_index: u32,
+    current_pulse_len: u32,
     current_pulse_sample_index: u32,
+    clock: SampleClock,
 }
 
 impl PilotWaveform {
     pub fn new(config: Arc<Config>, length_pulse: u16, length_tone: u16, start_pulse_high: bool) -> Self {
         let current_pulse = Pulse::new(config.clone(), length_pulse, start_pulse_high);
+        let mut clock = SampleClock::new();
+        let current_pulse_len = clock.samples(&current_pulse);
 
         return Self {
             config,
             length_pulse,
             length_tone,
+            start_pulse_high,
             current_pulse,
             current_pulse_index: 0,
+            current_pulse_len,
             current_pulse_sample_index: 0,
+            clock,
         }
     }
 
     fn update_pulse(&mut self) {
         self.current_pulse.high = !self.current_pulse.high;
+        self.current_pulse_len = self.clock.samples(&self.current_pulse);
     }
 }
 
@@ -54,10 +63,9 @@ impl Iterator for PilotWaveform {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.current_pulse_index < self.length_tone as u32 {
-            let pulse_sample = self.current_pulse.next_sample(self.current_pulse_sample_index);
-            if pulse_sample.is_some() {
+            if self.current_pulse_sample_index < self.current_pulse_len {
                 self.current_pulse_sample_index += 1;
-                return pulse_sample;
+                return Some(self.current_pulse.sample());
             }
 
             self.current_pulse_index += 1;
@@ -79,23 +87,23 @@ impl Source for PilotWaveform {
         if self.length_tone == 0 {
             return None;
         }
-        return Some(self.current_pulse.duration() * self.length_tone as u32);
+        return Some(self.config.t_cycles_duration(0, self.length_pulse as u64 * self.length_tone as u64));
     }
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
-        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u32;
-        let mut pulse_samples = 0;
-        self.current_pulse_index = 0;
-        self.current_pulse_sample_index = 0;
-        while pulse_samples < samples && self.current_pulse_index < self.length_tone as u32 {
-            pulse_samples += self.current_pulse.len();
-            if pulse_samples > samples {
-                self.current_pulse_sample_index = pulse_samples - samples;
-            } else {
-                self.current_pulse_index += 1;
-                self.update_pulse()
-            }
-        }
+        if self.length_tone == 0 { return Ok(()) }
+        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u64;
+
+        // Pulses all have the same length, so the pulse played at the given position can be found directly. Seeking
+        // past the end leaves the last pulse fully played.
+        let length_pulse = self.length_pulse as u64;
+        let pulse_index = SampleClock::seek_index(&self.config, 0, samples, self.length_tone as usize, |index| index as u64 * length_pulse);
+        let t_cycles = pulse_index as u64 * length_pulse;
+        self.current_pulse_index = pulse_index as u32;
+        self.current_pulse.high = self.start_pulse_high ^ (pulse_index % 2 == 1);
+        self.clock = SampleClock::following(t_cycles);
+        self.current_pulse_len = self.clock.samples(&self.current_pulse);
+        self.current_pulse_sample_index = std::cmp::min(samples - SampleClock::offset(&self.config, 0, t_cycles), self.current_pulse_len as u64) as u32;
         return Ok(());
     }
 }
@@ -107,6 +115,8 @@ impl Waveform for PilotWaveform {
 
     fn started(&self) -> bool { self.current_pulse_index > 0 || self.current_pulse_sample_index > 0 }
 
+    fn is_pilot(&self) -> bool { true }
+
     fn visualise(&self, pulse_string_length: usize) -> String {
         let mut pulse_string = "".to_string();
         let mut pulse_index = self.current_pulse_index;
@@ -53,8 +53,17 @@ impl Pulse {
     /// the length of a one pulse due to this rounding. For example, if a none-rounded zero and one would be 13.6
     /// and 27.2 samples long, then when rounded these would be 14 samples for the zero and 27 for the one, making
     /// the one pulse shorter than intended. For longer blocks this drift can cause the block to fail.
+    ///
+    /// Waveforms therefore use a [SampleClock] when playing pulses, which carries the rounding error over from one
+    /// pulse to the next.
     pub fn len(&self) -> u32 {
-        return (self.length as f64 * self.config.platform.t_cycle_secs_playback(self.config.playback_duration_percent) * self.config.sample_rate as f64).round() as u32
+        return self.exact_len().round() as u32
+    }
+
+    /// Returns the exact, fractional length of the pulse in samples for the configured playback duration adjustment
+    /// and sample rate.
+    pub fn exact_len(&self) -> f64 {
+        return self.length as f64 * self.config.samples_per_t_cycle()
     }
 
     /// Returns the duration of the pulse for the configured playback duration adjustment and sample rate.
@@ -83,3 +92,100 @@ impl fmt::Display for Pulse {
         write!(f, "{}{}", self.length, if self.high { 'h' } else { 'l' })
     }
 }
+
+/// Converts pulse lengths to whole numbers of samples, carrying the rounding error of each pulse over to the next.
+///
+/// Rounding each pulse independently (see [Pulse::len]) lets the error accumulate over a block. The clock instead
+/// rounds the running total, so the start of every pulse is within half a sample of its exact position and total
+/// block durations match the t cycle totals at any sample rate. Where a block is played by several waveforms, e.g. a
+/// pilot tone followed by data, later waveforms continue the clock with [SampleClock::following].
+///
+/// ```
+/// use std::sync::Arc;
+/// use rtzx::tzx::Config;
+/// use rtzx::tzx::waveforms::{Pulse, pulse::SampleClock};
+///
+/// // At 44100Hz a 855 t cycle zero pulse is around 10.77 samples long.
+/// let pulse = Pulse::new(Arc::new(Config::builder().build()), 855, true);
+/// let mut clock = SampleClock::new();
+/// let total: u32 = (0..100).map(|_| clock.samples(&pulse)).sum();
+/// assert_eq!(total, (pulse.exact_len() * 100.0).round() as u32);
+/// assert_ne!(total, pulse.len() * 100);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SampleClock {
+    /// The number of t cycles played so far.
+    t_cycles: u64,
+}
+
+impl SampleClock {
+    /// SampleClock constructor.
+    pub fn new() -> Self { Self::default() }
+
+    /// Returns a clock continuing on from the given number of t cycles of preceding pulses.
+    pub fn following(t_cycles: u64) -> Self {
+        return Self { t_cycles }
+    }
+
+    /// Returns the number of t cycles played so far, including any preceding pulses the clock was continued from.
+    pub fn t_cycles(&self) -> u64 { self.t_cycles }
+
+    /// Returns the number of samples to play for the given pulse, following on from the previous pulses.
+    pub fn samples(&mut self, pulse: &Pulse) -> u32 {
+        let samples = Self::offset(&pulse.config, self.t_cycles, pulse.length as u64);
+        self.t_cycles += pulse.length as u64;
+        return samples as u32;
+    }
+
+    /// Returns the number of samples played for `t_cycles` of pulses following on from `start` t cycles of preceding
+    /// pulses.
+    ///
+    /// Waveforms use this when seeking to find the pulse that playback would have reached.
+    pub fn offset(config: &Config, start: u64, t_cycles: u64) -> u64 {
+        let samples = |t_cycles: u64| (t_cycles as f64 * config.samples_per_t_cycle()).round() as u64;
+        return samples(start + t_cycles) - samples(start);
+    }
+
+    /// Returns the last of `count` positions in a waveform, e.g. pulses or bytes, that starts at or before the sample
+    /// `samples` into the waveform. `t_cycles` gives the number of t cycles into the waveform at which each position
+    /// starts, and `start` is the number of t cycles preceding the waveform in its block.
+    pub fn seek_index(config: &Config, start: u64, samples: u64, count: usize, t_cycles: impl Fn(usize) -> u64) -> usize {
+        let mut low = 0;
+        let mut high = count;
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            if Self::offset(config, start, t_cycles(middle)) <= samples {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        return low;
+    }
+
+    /// Finds the pulse playing the sample `samples` into a waveform by walking `pulses`, which start `t_cycles` into
+    /// the waveform at or before the target sample. `start` is the number of t cycles preceding the waveform in its
+    /// block.
+    ///
+    /// Returns the pulse found, the number of t cycles preceding it in the waveform, and the number of its samples
+    /// already played. If the pulses end first, the last pulse is returned with all of its samples played. Returns
+    /// `None` if there are no pulses.
+    pub fn seek(
+        config: &Config,
+        start: u64,
+        mut t_cycles: u64,
+        samples: u64,
+        pulses: impl Iterator<Item = Pulse>
+    ) -> Option<(Pulse, u64, u64)> {
+        let mut found = None;
+        for pulse in pulses {
+            let length = pulse.length as u64;
+            let pulse_start = Self::offset(config, start, t_cycles);
+            let pulse_end = Self::offset(config, start, t_cycles + length);
+            found = Some((pulse, t_cycles, samples.clamp(pulse_start, pulse_end) - pulse_start));
+            if samples < pulse_end { break }
+            t_cycles += length;
+        }
+        return found;
+    }
+}
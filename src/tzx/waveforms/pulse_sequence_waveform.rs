@@ -10,7 +10,7 @@ use std::time::Duration;
 
 use crate::tzx::{
     Config,
-    waveforms::{Pulse, Waveform},
+    waveforms::{Pulse, Waveform, pulse::SampleClock},
 };
 
 /// A waveform for encoding a sequence of pulses as used by [PulseSequence](crate::tzx::blocks::PulseSequence).
@@ -24,16 +24,21 @@ pub struct PulseSequenceWaveform {
     current_pulse_index: usize,
     current_pulse_sample_index: u32,
     pulses: Vec<Pulse>,
+    pulse_lens: Vec<u32>,
 }
 
 impl PulseSequenceWaveform {
     pub fn new(config: Arc<Config>, pulse_lengths: &Vec<u16>,  start_pulse_high: bool) -> Self {
         let mut current_pulse_high = start_pulse_high;
         let mut pulses: Vec<Pulse> = vec![];
+        let mut pulse_lens: Vec<u32> = vec![];
         let mut total_length: usize = 0;
+        let mut clock = SampleClock::new();
 
         for pulse_length in pulse_lengths {
-            pulses.push(Pulse::new(config.clone(), *pulse_length, current_pulse_high));
+            let pulse = Pulse::new(config.clone(), *pulse_length, current_pulse_high);
+            pulse_lens.push(clock.samples(&pulse));
+            pulses.push(pulse);
             total_length += *pulse_length as usize;
             current_pulse_high = !current_pulse_high;
         }
@@ -44,6 +49,7 @@ impl PulseSequenceWaveform {
             current_pulse_index: 0,
             current_pulse_sample_index: 0,
             pulses: pulses,
+            pulse_lens,
         }
     }
 }
@@ -53,16 +59,15 @@ impl Iterator for PulseSequenceWaveform {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.current_pulse_index < self.pulses.len() {
-            let pulse_sample = self.pulses[self.current_pulse_index].next_sample(self.current_pulse_sample_index);
-            if pulse_sample.is_some() {
+            if self.current_pulse_sample_index < self.pulse_lens[self.current_pulse_index] {
                 self.current_pulse_sample_index += 1;
-                return pulse_sample;
+                return Some(self.pulses[self.current_pulse_index].sample());
             }
 
             self.current_pulse_index += 1;
             self.current_pulse_sample_index = 0;
             if self.current_pulse_index < self.pulses.len() {
-                return self.pulses[self.current_pulse_index].next_sample(self.current_pulse_sample_index)
+                return self.next()
             }
         }
         return None;
@@ -75,26 +80,21 @@ impl Source for PulseSequenceWaveform {
     fn current_span_len(&self) -> Option<usize> { None }
 
     fn total_duration(&self) -> Option<Duration> {
-        let mut duration = Duration::ZERO;
-        for pulse in &self.pulses {
-            duration += pulse.duration();
-        }
-        return Some(duration);
+        return Some(self.config.t_cycles_duration(0, self.total_length as u64));
     }
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
-        let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u32;
-        let mut pulse_samples = 0;
+        if self.pulses.is_empty() { return Ok(()) }
+        let mut samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u32;
+
+        // Walk the pulses to the one played at the given position. Seeking past the end leaves the last pulse fully
+        // played.
         self.current_pulse_index = 0;
-        self.current_pulse_sample_index = 0;
-        while pulse_samples < samples && self.current_pulse_index < self.pulses.len() {
-            pulse_samples += self.pulses[self.current_pulse_index].len();
-            if pulse_samples >= samples {
-                self.current_pulse_sample_index = pulse_samples - samples;
-            } else {
-                self.current_pulse_index += 1;
-            }
+        while self.current_pulse_index < self.pulses.len() - 1 && samples >= self.pulse_lens[self.current_pulse_index] {
+            samples -= self.pulse_lens[self.current_pulse_index];
+            self.current_pulse_index += 1;
         }
+        self.current_pulse_sample_index = std::cmp::min(samples, self.pulse_lens[self.current_pulse_index]);
         return Ok(());
     }
 }
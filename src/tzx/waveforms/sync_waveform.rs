@@ -10,7 +10,7 @@ use std::time::Duration;
 
 use crate::tzx::{
     Config,
-    waveforms::{Pulse, Waveform},
+    waveforms::{Pulse, Waveform, pulse::SampleClock},
 };
 
 /// A waveform for encoding sync pulses as used by [StandardSpeedDataBlock](crate::tzx::blocks::StandardSpeedDataBlock)
@@ -28,20 +28,42 @@ pub struct SyncWaveform {
     current_pulse_sample_index: u32,
     pulse_first: Pulse,
     pulse_second: Pulse,
+    pulse_first_len: u32,
+    pulse_second_len: u32,
+    start_t_cycles: u64,
 }
 
 impl SyncWaveform {
     pub fn new(config: Arc<Config>, length_pulse_sync_first: u16, length_pulse_sync_second: u16, start_pulse_high: bool) -> Self {
+        let pulse_first = Pulse::new(config.clone(), length_pulse_sync_first, start_pulse_high);
+        let pulse_second = Pulse::new(config.clone(), length_pulse_sync_second, !start_pulse_high);
+        let mut clock = SampleClock::new();
+        let pulse_first_len = clock.samples(&pulse_first);
+        let pulse_second_len = clock.samples(&pulse_second);
+
         return Self {
             config: config.clone(),
             length_pulse_sync_first,
             length_pulse_sync_second,
             is_first_pulse: true,
             current_pulse_sample_index: 0,
-            pulse_first: Pulse::new(config.clone(), length_pulse_sync_first, start_pulse_high),
-            pulse_second: Pulse::new(config.clone(), length_pulse_sync_second, !start_pulse_high),
+            pulse_first,
+            pulse_second,
+            pulse_first_len,
+            pulse_second_len,
+            start_t_cycles: 0,
         }
     }
+
+    /// Continues on from the given number of t cycles of preceding pulses in the block, carrying the rounding of
+    /// pulses to whole samples over from them (see [SampleClock]).
+    pub fn following(mut self, t_cycles: u64) -> Self {
+        let mut clock = SampleClock::following(t_cycles);
+        self.start_t_cycles = t_cycles;
+        self.pulse_first_len = clock.samples(&self.pulse_first);
+        self.pulse_second_len = clock.samples(&self.pulse_second);
+        return self;
+    }
 }
 
 impl Iterator for SyncWaveform {
@@ -49,18 +71,19 @@ impl Iterator for SyncWaveform {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.is_first_pulse {
-            let pulse_sample = self.pulse_first.next_sample(self.current_pulse_sample_index);
-            if pulse_sample.is_some() {
+            if self.current_pulse_sample_index < self.pulse_first_len {
                 self.current_pulse_sample_index += 1;
-                return pulse_sample;
+                return Some(self.pulse_first.sample());
             }
 
             self.is_first_pulse = false;
             self.current_pulse_sample_index = 0;
         }
-        let pulse_sample = self.pulse_second.next_sample(self.current_pulse_sample_index);
-        self.current_pulse_sample_index += 1;
-        return pulse_sample;
+        if self.current_pulse_sample_index < self.pulse_second_len {
+            self.current_pulse_sample_index += 1;
+            return Some(self.pulse_second.sample());
+        }
+        return None;
     }
 }
 
@@ -71,20 +94,18 @@ impl Source for SyncWaveform {
 
     fn total_duration(&self) -> Option<Duration> {
         //return Some(Duration::from_secs_f64((self.pulse_first.len() as f64 + self.pulse_second.len() as f64) / 48000.0));
-        return Some(self.pulse_first.duration() + self.pulse_second.duration());
+        return Some(self.config.t_cycles_duration(self.start_t_cycles, self.length_pulse_sync_first as u64 + self.length_pulse_sync_second as u64));
     }
 
     fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
         let samples = (pos.as_secs_f64() * self.config.sample_rate as f64).round() as u32;
-        self.current_pulse_sample_index = 0;
-        if samples < self.pulse_first.len() {
-            self.is_first_pulse = true;
-            self.current_pulse_sample_index = samples;
+        self.is_first_pulse = samples < self.pulse_first_len;
+        self.current_pulse_sample_index = if self.is_first_pulse {
+            samples
         } else {
-            self.is_first_pulse = true;
-            self.current_pulse_sample_index = samples - self.pulse_first.len();
-        }
-       return Ok(());
+            std::cmp::min(samples - self.pulse_first_len, self.pulse_second_len)
+        };
+        return Ok(());
     }
 }
 
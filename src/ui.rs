@@ -1 +1,2 @@
+pub mod block_list;
 pub mod commands;
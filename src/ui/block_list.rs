@@ -0,0 +1,158 @@
+//! A full screen list of the blocks of a tape, for browsing and jumping to blocks during playback.
+
+use crossterm::{
+    event::{self, Event, KeyCode},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    style::{Color, Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, Borders, List, ListItem, ListState},
+    Terminal,
+};
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::tzx::{
+    self,
+    BlockGroup,
+    ExtendedDisplayCollector,
+    Player,
+    blocks::{ArchiveInfo, GroupEnd},
+};
+use crate::ui::commands::play::format_duration;
+
+/// The number of blocks moved by the page up and page down keys.
+const PAGE_BLOCKS: usize = 10;
+
+/// Collects the entries of an [ArchiveInfo] block as indented lines.
+struct ArchiveInfoCollector<'a> {
+    indent: String,
+    lines: Vec<Line<'a>>,
+}
+
+impl ExtendedDisplayCollector for ArchiveInfoCollector<'_> {
+    fn push(&mut self, item: &dyn fmt::Display) {
+        self.lines.push(Line::from(format!("{}    {}", self.indent, item)).fg(Color::Gray));
+    }
+}
+
+/// Shows the blocks of the tape in a full screen list while playback continues, with the block currently playing
+/// marked. Blocks within groups are indented by their nesting depth, and the entries of [ArchiveInfo] blocks are
+/// listed beneath them.
+///
+/// The list is navigated with the arrow, page up / page down, home and end keys. Returns the index of the block
+/// selected with enter, or `None` if the list is closed with escape, `B` or `Q`.
+pub fn browse_blocks(player: &mut Player) -> io::Result<Option<usize>> {
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let mut state = ListState::default().with_selected(Some(player.current_block_index));
+    let last_index = player.len_blocks().saturating_sub(1);
+
+    let selected = loop {
+        player.tick();
+
+        let items = list_items(player);
+        terminal.draw(|f| {
+            let list = List::new(items)
+                .block(Block::default()
+                    .title(" Blocks ".green().bold())
+                    .title_bottom(Line::from(vec![
+                        " Select ".into(),
+                        "<↑/↓>".blue().bold(),
+                        " Seek to block ".into(),
+                        "<ENTER>".blue().bold(),
+                        " Close ".into(),
+                        "<ESC> ".blue().bold(),
+                    ]).centered())
+                    .borders(Borders::ALL))
+                .highlight_style(Style::default().bg(Color::LightBlue).fg(Color::Black));
+            f.render_stateful_widget(list, f.area(), &mut state);
+        })?;
+
+        if event::poll(Duration::from_millis(20))?
+            && let Event::Key(key) = event::read()?
+            && key.is_press() {
+            let selected_index = state.selected().unwrap_or(0);
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('b') | KeyCode::Char('q') => break None,
+                KeyCode::Enter => break Some(selected_index),
+                KeyCode::Up => state.select(Some(selected_index.saturating_sub(1))),
+                KeyCode::Down => state.select(Some((selected_index + 1).min(last_index))),
+                KeyCode::PageUp => state.select(Some(selected_index.saturating_sub(PAGE_BLOCKS))),
+                KeyCode::PageDown => state.select(Some((selected_index + PAGE_BLOCKS).min(last_index))),
+                KeyCode::Home => state.select(Some(0)),
+                KeyCode::End => state.select(Some(last_index)),
+                _ => (),
+            }
+        }
+    };
+
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    return Ok(selected);
+}
+
+/// Returns the number of groups that the block at the given index is within. The start and end blocks of a group are
+/// not within it, but the last block of a group that is not ended is.
+fn group_depth(groups: &[BlockGroup], index: usize, block: &dyn tzx::blocks::Block) -> usize {
+    let is_group_end = block.as_any().is::<GroupEnd>();
+    return groups.iter()
+        .filter(|group| group.start < index && group.contains(index) && !(index == group.end && is_group_end))
+        .count();
+}
+
+/// Returns a list item for each block, indented by the number of the player's groups that it is within.
+fn list_items(player: &Player) -> Vec<ListItem<'static>> {
+    let mut items = Vec::new();
+
+    for (index, block) in player.blocks.iter().enumerate() {
+        let indent = "  ".repeat(group_depth(&player.groups, index, block.as_ref()));
+        let marker = if index == player.current_block_index { "▶" } else { " " };
+        let mut lines = vec![Line::from(vec![
+            format!("{} ", marker).green().bold(),
+            format!("{:03}", index + 1).bold(),
+            format!("{:12}", format_duration(player.block_durations[index])).yellow(),
+            format!("  {}{}", indent, block).into(),
+        ])];
+
+        if block.as_any().is::<ArchiveInfo>() {
+            let mut collector = ArchiveInfoCollector { indent: " ".repeat(19) + &indent, lines: vec![] };
+            block.extended_display(&mut collector);
+            lines.extend(collector.lines);
+        }
+        items.push(ListItem::new(Text::from(lines)));
+    }
+
+    return items;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tzx::{
+        blocks::{Block, GroupStart, PureTone},
+        player::block_groups,
+    };
+
+    #[test]
+    fn blocks_are_indented_by_the_groups_they_are_within() {
+        let blocks: Vec<Box<dyn Block>> = vec![
+            Box::new(GroupStart::new("Loader")),
+            Box::new(PureTone::new(1000, 100)),
+            Box::new(GroupEnd::new()),
+            Box::new(GroupEnd::new()),
+            Box::new(GroupStart::new("Levels")),
+            Box::new(GroupStart::new("Level 1")),
+            Box::new(PureTone::new(1000, 100)),
+        ];
+        let groups = block_groups(&blocks);
+        let depths: Vec<usize> = blocks.iter().enumerate()
+            .map(|(index, block)| group_depth(&groups, index, block.as_ref()))
+            .collect();
+        assert_eq!(depths, vec![0, 1, 0, 0, 0, 1, 2]);
+    }
+}
@@ -1,25 +1,45 @@
 pub mod convert;
+pub mod edit;
 pub mod inspect;
+pub mod merge;
+pub mod optimise;
 pub mod play;
+pub mod split;
 
 pub use convert::run_convert;
+pub use edit::run_edit;
 pub use inspect::run_inspect;
+pub use merge::run_merge;
+pub use optimise::run_optimise;
 pub use play::run_play;
+pub use split::run_split;
 
-use clap::{Args, Subcommand};
+use clap::{ArgGroup, Args, Subcommand, ValueEnum};
 use rodio::SampleRate;
-use std::path::PathBuf;
+use std::fmt;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use crate::tzx::{Config, Platform};
+use crate::tzx::{Config, ParseMode, Platform, output::{OutputChannels, OutputShape}};
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Convert a tape file to wav
     Convert(ConvertArgs),
+    /// Edit the blocks of a tape file
+    #[command(subcommand)]
+    Edit(EditCommands),
     /// Inspect a tape file
     Inspect(InspectArgs),
+    /// Merge several tape files into one
+    Merge(MergeArgs),
+    /// Replace direct recordings with equivalent data blocks
+    Optimise(OptimiseArgs),
     /// Play a tape file
     Play(PlayArgs),
+    /// Split a tape file into several files
+    Split(SplitArgs),
 }
 
 impl Commands {
@@ -28,9 +48,43 @@ impl Commands {
             Commands::Inspect(args) => Some(args.file.file_name.clone()),
             Commands::Play(args) => Some(args.file.file_name.clone()),
             Commands::Convert(args) => Some(args.file.file_name.clone()),
+            Commands::Edit(command) => Some(command.file().file_name.clone()),
+            Commands::Merge(_) => None,
+            Commands::Optimise(args) => Some(args.file.file_name.clone()),
+            Commands::Split(args) => Some(args.file.file_name.clone()),
         }
     }
 
+    pub fn parse_mode(&self) -> ParseMode {
+        return match self {
+            Commands::Inspect(args) => args.file.parse.parse_mode(),
+            Commands::Play(args) => args.file.parse.parse_mode(),
+            Commands::Convert(args) => args.file.parse.parse_mode(),
+            Commands::Edit(command) => command.file().parse.parse_mode(),
+            Commands::Merge(args) => args.parse.parse_mode(),
+            Commands::Optimise(args) => args.file.parse.parse_mode(),
+            Commands::Split(args) => args.file.parse.parse_mode(),
+        };
+    }
+
+    /// Returns whether the command writes its output over the file it reads.
+    pub fn overwrites_input(&self) -> bool {
+        return match self {
+            Commands::Edit(command) => command.output().is_some_and(OutputArgs::is_in_place),
+            Commands::Optimise(args) => args.output.is_in_place(),
+            _ => false,
+        };
+    }
+
+    /// Checks that any blocks given to the command's options exist, given the number of blocks in the file.
+    pub fn check_blocks(&self, block_count: usize) -> Result<(), String> {
+        return match self {
+            Commands::Play(args) => args.config.check_blocks(block_count),
+            Commands::Convert(args) => args.config.check_blocks(block_count),
+            _ => Ok(()),
+        };
+    }
+
     pub fn config(&self) -> Config {
         #[allow(unreachable_patterns)]
         match self {
@@ -65,26 +119,127 @@ pub struct ConfigArgs {
     ///
     /// Positive numbers increase playback duration. Defaults to zero (standard playback
     /// speed) if not supplied.
+    ///
+    /// May be given for a block or range of blocks as numbered by inspect, e.g. -d 5:+10 -d 12..20:+15, and
+    /// repeated. An adjustment for a block always takes precedence over one for the whole tape, whatever their order;
+    /// otherwise later adjustments take precedence.
     #[arg(short = 'd', long)]
-    playback_duration_percent: Option<i32>,
+    playback_duration_percent: Vec<PlaybackDuration>,
+
+    /// The shape of output pulses. Defaults to square if not supplied.
+    ///
+    /// Shaped edges can help with audio hardware that rings on square waves, and with loaders that have weak edge
+    /// detectors.
+    #[arg(long, value_enum)]
+    output_shape: Option<OutputShape>,
+
+    /// The rise time of edges in microseconds for trapezoid pulses. Defaults to 50 if not supplied.
+    #[arg(long)]
+    rise_time_us: Option<u32>,
+
+    /// The output amplitude in dBFS, e.g. -6. Defaults to 0 (full scale) if not supplied.
+    #[arg(long, allow_hyphen_values = true)]
+    amplitude_db: Option<f32>,
+
+    /// Invert the polarity of the output, for interfaces that invert the signal.
+    #[arg(long, default_value_t = false)]
+    invert: bool,
+
+    /// The level of silent pauses as a proportion of full scale from -1.0 to 1.0, i.e. a DC offset while the tape
+    /// is idle. Defaults to 0 if not supplied.
+    ///
+    /// The level is part of the signal, so it is scaled by --amplitude-db and inverted by --invert.
+    #[arg(long, allow_hyphen_values = true)]
+    pause_level: Option<f32>,
+
+    /// The output channels. Defaults to mono if not supplied.
+    ///
+    /// Differential output puts the inverted signal on the right channel, roughly doubling the swing into a tape
+    /// input connected across both channels.
+    #[arg(long, value_enum)]
+    channels: Option<OutputChannels>,
+
+    /// Replace any silence at the start of the tape with a pause of this length in ms, e.g. to give a real machine
+    /// time to settle after pressing PLAY. Use 0 to remove leading silence.
+    #[arg(long)]
+    initial_silence_ms: Option<u16>,
+
+    /// The minimum number of pilot pulses for standard and turbo speed data blocks. Shorter pilot tones are
+    /// lengthened.
+    #[arg(long)]
+    min_pilot_pulses: Option<u16>,
+
+    /// The minimum length in ms of pauses between blocks. Shorter pauses are lengthened, but zero length pauses are
+    /// left unchanged.
+    #[arg(long)]
+    min_gap_ms: Option<u16>,
 }
 
 impl ConfigArgs {
+    /// Checks that the blocks given for per-block playback duration adjustments exist, given the number of blocks.
+    pub fn check_blocks(&self, block_count: usize) -> Result<(), String> {
+        for blocks in self.playback_duration_percent.iter().filter_map(|duration| duration.blocks) {
+            blocks.indices(block_count)?;
+        }
+        return Ok(());
+    }
+
     pub fn to_config(&self, file_name: PathBuf) -> Config {
         return Config::builder()
             .maybe_platform(self.platform.clone().or(Platform::from_path(file_name)))
             .maybe_sample_rate(self.sample_rate)
             .maybe_buffer_length_ms(self.buffer_length_ms)
-            .maybe_playback_duration_percent(self.playback_duration_percent)
+            .maybe_playback_duration_percent(self.playback_duration_percent.iter()
+                .filter(|duration| duration.blocks.is_none())
+                .map(|duration| duration.percent)
+                .next_back())
+            .block_playback_duration_percent(self.playback_duration_percent.iter()
+                .filter_map(|duration| duration.blocks.map(|blocks| (blocks, duration.percent)))
+                .flat_map(|(blocks, percent)| (blocks.first - 1..blocks.last).map(move |index| (index, percent)))
+                .collect())
+            .maybe_output_shape(self.output_shape)
+            .maybe_rise_time_us(self.rise_time_us)
+            .maybe_amplitude_db(self.amplitude_db)
+            .invert(self.invert)
+            .maybe_pause_level(self.pause_level.map(|pause_level| pause_level.clamp(-1.0, 1.0)))
+            .maybe_channels(self.channels)
+            .maybe_initial_silence_ms(self.initial_silence_ms)
+            .maybe_min_pilot_pulses(self.min_pilot_pulses)
+            .maybe_min_gap_ms(self.min_gap_ms)
             .build();
     }
 }
 
+#[derive(Args)]
+pub struct ParseArgs {
+    /// Fail on the first block that cannot be parsed, rather than skipping it with a warning.
+    #[arg(long, default_value_t = false, conflicts_with = "recover")]
+    strict: bool,
+
+    /// Resynchronise on the next plausible block after any block that cannot be parsed, keeping the damaged
+    /// region, rather than skipping a single byte.
+    #[arg(long, default_value_t = false)]
+    recover: bool,
+}
+
+impl ParseArgs {
+    pub fn parse_mode(&self) -> ParseMode {
+        return match (self.strict, self.recover) {
+            (true, _) => ParseMode::Strict,
+            (_, true) => ParseMode::Recover,
+            _ => ParseMode::Lenient,
+        };
+    }
+}
+
 // Todo: ensure Inspect play still sniffs platform from filename?
 #[derive(Args)]
 pub struct FileArgs {
     /// The tape file (tzx / cdt)
     file_name: PathBuf,
+
+    #[command(flatten)]
+    parse: ParseArgs,
 }
 
 #[derive(Args)]
@@ -98,6 +253,41 @@ pub struct ConvertArgs {
     /// The filename to output to. Defaults to the same name as the tzx / cdt file with a .wav extension if not supplied.
     #[arg(short, long)]
     output_file_name: Option<PathBuf>,
+
+    /// The number of bits per sample: 8, 16, 24 or 32 for integer samples, or 32 for float samples. Defaults to 16
+    /// for integer samples and 32 for float samples if not supplied.
+    #[arg(long)]
+    pub bit_depth: Option<u16>,
+
+    /// The format of samples.
+    #[arg(long, value_enum, default_value_t = WavSampleFormat::Int)]
+    pub sample_format: WavSampleFormat,
+
+    /// Write a numbered wav file for each block, group or section ending in a stop tape command, e.g. out-1.wav,
+    /// out-2.wav.
+    #[arg(long, value_enum)]
+    pub split_by: Option<WavSplitBy>,
+}
+
+/// Where to split converted wav files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WavSplitBy {
+    /// After each stop tape command (zero length pause).
+    Stop,
+    /// Before each group start and after each group end, so that each group is in a file of its own.
+    Group,
+    /// Before each block that produces a signal. Pauses stay with the block before them, and other blocks without a
+    /// signal, such as groups and text descriptions, with the block that follows them.
+    Block,
+}
+
+/// The format of samples in converted wav files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WavSampleFormat {
+    /// Integer PCM samples. 8 bit samples are unsigned, as required by the wav format.
+    Int,
+    /// 32 bit floating point samples.
+    Float,
 }
 
 #[derive(Args)]
@@ -118,3 +308,338 @@ pub struct PlayArgs {
     #[command(flatten)]
     file: FileArgs,
 }
+
+/// A playback duration adjustment, optionally restricted to a [BlockRange].
+///
+/// Parsed from a percentage (e.g. `+10`), or a block range and a percentage separated by a colon (e.g. `12..20:+15`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlaybackDuration {
+    pub blocks: Option<BlockRange>,
+    pub percent: i32,
+}
+
+impl FromStr for PlaybackDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (blocks, percent) = match s.split_once(':') {
+            Some((blocks, percent)) => (Some(blocks.parse::<BlockRange>()?), percent),
+            None => (None, s),
+        };
+        let percent = percent.trim().parse::<i32>()
+            .map_err(|_| format!("Invalid playback duration '{}': expected a percentage, e.g. +10", percent))?;
+
+        return Ok(PlaybackDuration { blocks, percent });
+    }
+}
+
+/// A range of blocks, numbered from 1 as displayed by inspect.
+///
+/// Parsed from either a single block number (e.g. `5`) or an inclusive range of block numbers (e.g. `5..7`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl BlockRange {
+    /// Returns the zero-based indices of the blocks in the range, checking that all blocks exist given the supplied
+    /// number of blocks.
+    pub fn indices(&self, block_count: usize) -> Result<Range<usize>, String> {
+        if self.last > block_count {
+            return Err(format!("Block range {} is out of bounds: there are {} blocks", self, block_count));
+        }
+        return Ok(self.first - 1..self.last);
+    }
+}
+
+impl fmt::Display for BlockRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}..{}", self.first, self.last)
+        }
+    }
+}
+
+impl FromStr for BlockRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_block_number = |s: &str| -> Result<usize, String> {
+            match s.trim().parse::<usize>() {
+                Ok(0) | Err(_) => Err(format!("Invalid block number '{}': block numbers start at 1", s)),
+                Ok(number) => Ok(number),
+            }
+        };
+
+        let (first, last) = match s.split_once("..") {
+            Some((first, last)) => (parse_block_number(first)?, parse_block_number(last)?),
+            None => {
+                let number = parse_block_number(s)?;
+                (number, number)
+            }
+        };
+
+        if first > last {
+            return Err(format!("Invalid block range '{}': the first block is after the last", s));
+        }
+
+        return Ok(BlockRange { first, last });
+    }
+}
+
+#[derive(Subcommand)]
+pub enum EditCommands {
+    /// Delete blocks
+    Rm(EditRmArgs),
+    /// Move a block to a new position
+    Mv(EditMvArgs),
+    /// Insert a block
+    Insert(EditInsertArgs),
+    /// Write a range of blocks to a new file
+    Extract(EditExtractArgs),
+    /// Convert turbo speed data blocks to generalized data blocks or back
+    Convert(EditConvertArgs),
+}
+
+impl EditCommands {
+    pub fn file(&self) -> &FileArgs {
+        match self {
+            EditCommands::Rm(args) => &args.file,
+            EditCommands::Mv(args) => &args.file,
+            EditCommands::Insert(args) => &args.file,
+            EditCommands::Extract(args) => &args.file,
+            EditCommands::Convert(args) => &args.file,
+        }
+    }
+
+    pub fn output(&self) -> Option<&OutputArgs> {
+        match self {
+            EditCommands::Rm(args) => Some(&args.output),
+            EditCommands::Mv(args) => Some(&args.output),
+            EditCommands::Insert(args) => Some(&args.output),
+            EditCommands::Extract(_) => None,
+            EditCommands::Convert(args) => Some(&args.output),
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// The filename to output to. Defaults to overwriting the tzx / cdt file if not supplied, unless any blocks
+    /// were skipped while parsing it.
+    #[arg(short, long)]
+    output_file_name: Option<PathBuf>,
+}
+
+impl OutputArgs {
+    /// Returns the output filename, or the supplied input filename if none was given.
+    pub fn output_file_name<'a>(&'a self, file_name: &'a Path) -> &'a Path {
+        self.output_file_name.as_deref().unwrap_or(file_name)
+    }
+
+    /// Returns whether the output overwrites the input file, i.e. no output filename was given.
+    pub fn is_in_place(&self) -> bool { self.output_file_name.is_none() }
+}
+
+#[derive(Args)]
+pub struct EditRmArgs {
+    #[command(flatten)]
+    file: FileArgs,
+
+    /// The blocks to delete: a block number or inclusive range of block numbers (e.g. 5..7) as shown by inspect.
+    pub blocks: BlockRange,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+pub struct EditMvArgs {
+    #[command(flatten)]
+    file: FileArgs,
+
+    /// The number of the block to move.
+    pub from: usize,
+
+    /// The number the block will have after moving.
+    pub to: usize,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("block").required(true).args(["pause", "stop_tape", "text", "group_start", "group_end"])))]
+pub struct EditInsertArgs {
+    #[command(flatten)]
+    file: FileArgs,
+
+    /// The number the inserted block will have. Use one more than the number of blocks to append.
+    pub position: usize,
+
+    /// Insert a pause block of the given length in ms.
+    #[arg(long)]
+    pub pause: Option<u16>,
+
+    /// Insert a stop tape command.
+    #[arg(long, default_value_t = false)]
+    pub stop_tape: bool,
+
+    /// Insert a text description block.
+    #[arg(long)]
+    pub text: Option<String>,
+
+    /// Insert a group start block with the given name.
+    #[arg(long)]
+    pub group_start: Option<String>,
+
+    /// Insert a group end block.
+    #[arg(long, default_value_t = false)]
+    pub group_end: bool,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+pub struct EditExtractArgs {
+    #[command(flatten)]
+    file: FileArgs,
+
+    /// The blocks to extract: a block number or inclusive range of block numbers (e.g. 2..4) as shown by inspect.
+    pub blocks: BlockRange,
+
+    /// The filename to write the extracted blocks to.
+    pub output_file_name: PathBuf,
+}
+
+/// The block type to convert blocks to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EditConvertTo {
+    /// Convert turbo speed data blocks (0x11) to generalized data blocks (0x19).
+    Generalized,
+    /// Convert generalized data blocks (0x19) to turbo speed data blocks (0x11) where possible.
+    Turbo,
+}
+
+#[derive(Args)]
+pub struct EditConvertArgs {
+    #[command(flatten)]
+    file: FileArgs,
+
+    /// The blocks to convert: a block number or inclusive range of block numbers (e.g. 2..4) as shown by inspect.
+    /// Blocks of other types in the range are left unchanged.
+    pub blocks: BlockRange,
+
+    /// The block type to convert to.
+    #[arg(long, value_enum)]
+    pub to: EditConvertTo,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+/// How each side is labelled when merging tape files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum MergeLabel {
+    /// Wrap each side in a group named after its file.
+    Group,
+    /// Precede each side with a text description naming its file.
+    Text,
+}
+
+#[derive(Args)]
+pub struct MergeArgs {
+    /// The tape files to merge, in order.
+    #[arg(required = true, num_args = 2..)]
+    pub file_names: Vec<PathBuf>,
+
+    /// The filename to output to.
+    #[arg(short, long)]
+    pub output_file_name: PathBuf,
+
+    /// Label each side with a group or text description block naming its file.
+    #[arg(short, long, value_enum)]
+    pub label: Option<MergeLabel>,
+
+    /// Insert a stop tape command between sides.
+    #[arg(long, default_value_t = false)]
+    pub stop_between_sides: bool,
+
+    #[command(flatten)]
+    parse: ParseArgs,
+}
+
+/// Where to split a tape file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SplitBy {
+    /// After each stop tape command (zero length pause).
+    Stop,
+    /// Before each group start.
+    Group,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("split_points").required(true).args(["by", "at"])))]
+pub struct SplitArgs {
+    #[command(flatten)]
+    file: FileArgs,
+
+    /// Split at stop tape commands or group starts.
+    #[arg(short, long, value_enum)]
+    pub by: Option<SplitBy>,
+
+    /// Split before each of the given block numbers, as shown by inspect (e.g. --at 5,12).
+    #[arg(long, value_delimiter = ',')]
+    pub at: Vec<usize>,
+
+    /// The filename to base output filenames on. Pieces are numbered, e.g. out-1.tzx, out-2.tzx. Defaults to the
+    /// name of the tape file if not supplied.
+    #[arg(short, long)]
+    pub output_file_name: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct OptimiseArgs {
+    #[command(flatten)]
+    file: FileArgs,
+
+    /// The tolerance for matching pulse lengths, as a percentage.
+    ///
+    /// Pulses within one sample of the recording are always accepted to allow for quantisation.
+    #[arg(short, long, default_value_t = 15)]
+    pub tolerance_percent: u8,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(subcommand)]
+        command: Commands,
+    }
+
+    fn command(args: &[&str]) -> Commands {
+        return Cli::try_parse_from([&["rtzx"], args].concat()).unwrap().command;
+    }
+
+    #[test]
+    fn block_overrides_take_precedence_and_must_exist() {
+        let command = command(&["play", "-d", "2..3:+10", "-d", "+5", "tape.tzx"]);
+        let config = command.config();
+        assert_eq!(config.playback_duration_percent, 5);
+        assert_eq!(config.block_playback_duration_percent.get(&2), Some(&10));
+
+        assert!(command.check_blocks(3).is_ok());
+        assert!(command.check_blocks(2).is_err());
+    }
+}
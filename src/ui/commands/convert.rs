@@ -1,44 +1,198 @@
 use hound;
 use rodio::Source;
-use std::io;
+use std::cell::Cell;
+use std::fs::OpenOptions;
+use std::io::{self, ErrorKind, Seek, SeekFrom, Write};
+use std::path::Path;
 use std::sync::Arc;
 
 use crate::{
     Config,
     TapeDataFile,
 };
-use crate::ui::commands::ConvertArgs;
+use crate::tzx::{SplitPoints, TzxData, timing::{adjust_timings, block_waveforms}};
+use crate::tzx::blocks::{Block, GroupEnd, GroupStart, PauseOrStopTapeCommand};
+use crate::tzx::output::{OutputLevels, Shaper};
+use crate::ui::commands::{ConvertArgs, WavSampleFormat, WavSplitBy};
+use crate::ui::commands::split::numbered_file_name;
 
 pub fn run_convert(args: &ConvertArgs, config: &Config, tape_data: &TapeDataFile) -> io::Result<()> {
     let tzx_data = tape_data.tzx_data.as_ref().expect("TZX data missing!");
 
-    let spec = hound::WavSpec {
-        channels: 1,
-        sample_rate: config.sample_rate,
-        bits_per_sample: 16,
-        sample_format: hound::SampleFormat::Int,
-    };
+    let spec = wav_spec(args, config)?;
 
     let output_file_name = match &args.output_file_name {
         Some(file_name) => file_name,
         None => &args.file.file_name.with_extension("wav")
     };
 
-    let mut wav_writer = hound::WavWriter::create(output_file_name, spec).expect("Cannot open output wav file");
-
-    let config = Arc::new(config.clone());
-    let mut start_pulse_high = true;
-    for block in &tzx_data.blocks {
-        let waveforms = block.get_waveforms(config.clone(), start_pulse_high);
-        for waveform in waveforms {
-            let source: Box<dyn Source + Send> = waveform;
-            for sample in source {
-                let val = (sample * i16::MAX as f32) as i16;
-                wav_writer.write_sample(val).unwrap();
-            }
+    let split_points = match args.split_by {
+        Some(WavSplitBy::Stop) => SplitPoints::StopTape,
+        Some(WavSplitBy::Group) => SplitPoints::Groups,
+        Some(WavSplitBy::Block) => SplitPoints::Blocks(signal_block_split_points(&tzx_data.blocks)),
+        None => return write_wav(output_file_name, spec, config, tzx_data),
+    };
+
+    let pieces = tzx_data.split(&split_points);
+    let piece_indices = tzx_data.split_indices(&split_points);
+    let width = pieces.len().to_string().len();
+    for (index, (piece, indices)) in pieces.iter().zip(&piece_indices).enumerate() {
+        let file_name = numbered_file_name(output_file_name, index + 1, width, tape_data.file_type);
+        let piece_config = config.remap_blocks(|block_index| indices.iter().position(|index| *index == block_index));
+        write_wav(&file_name, spec, &piece_config, piece)?;
+        println!("{}: {} blocks", file_name.display(), piece.blocks.len());
+    }
+    return Ok(());
+}
+
+/// Returns the indices to split before so that each piece holds one block that produces a signal. Pauses stay with
+/// the block before them, and other blocks without a signal with the block after them.
+fn signal_block_split_points(blocks: &[Box<dyn Block>]) -> Vec<usize> {
+    let is_pause = |block: &dyn Block| block.as_any().is::<PauseOrStopTapeCommand>();
+
+    let mut split_points = Vec::new();
+    let mut previous_signal = None;
+    for (index, block) in blocks.iter().enumerate() {
+        if is_pause(block.as_ref()) || block.pulses(true).next().is_none() { continue }
+        if let Some(previous) = previous_signal {
+            let after_pauses = (previous + 1..index).find(|i| !is_pause(blocks[*i].as_ref())).unwrap_or(index);
+            split_points.push(after_pauses);
         }
-        start_pulse_high = block.next_block_start_pulse_high(config.clone(), start_pulse_high);
+        previous_signal = Some(index);
+    }
+    return split_points;
+}
+
+/// Writes the samples of the given data to a wav file with its timings adjusted, followed by a marker for each
+/// block.
+fn write_wav(file_name: &Path, spec: hound::WavSpec, config: &Config, tzx_data: &TzxData) -> io::Result<()> {
+    let tzx_data = &adjust_timings(tzx_data, config);
+    let mut wav_writer = hound::WavWriter::create(file_name, spec).expect("Cannot open output wav file");
+
+    let shared_config = Arc::new(config.clone());
+    let mut group: Option<String> = None;
+    let mut markers: Vec<(u32, String)> = Vec::new();
+    let sample_count = Cell::new(0u32);
+    let block_waveforms = block_waveforms(&tzx_data.blocks, &shared_config, 0);
+    let samples = tzx_data.blocks.iter().zip(block_waveforms).enumerate().flat_map(|(index, (block, waveforms))| {
+        // Blocks are only read as their samples are needed, so the count is that of the samples before the block.
+        markers.push((sample_count.get(), marker_label(index, block.as_ref(), &mut group)));
+        waveforms.into_iter()
+            .flat_map(|waveform| -> Box<dyn Source + Send> { waveform })
+            .inspect(|_| sample_count.set(sample_count.get() + 1))
+    });
+
+    // Shape the samples as a single stream, so that edges between blocks are shaped too.
+    for sample in config.channels.apply(OutputLevels::new(config).apply(Shaper::new(samples, config))) {
+        match spec.sample_format {
+            hound::SampleFormat::Float => wav_writer.write_sample(sample.clamp(-1.0, 1.0)),
+            hound::SampleFormat::Int => wav_writer.write_sample(quantise(sample, spec.bits_per_sample)),
+        }.unwrap();
     }
     wav_writer.finalize().unwrap();
+
+    write_markers(file_name, &markers)?;
+    return Ok(());
+}
+
+/// Returns the label of the marker for a block, prefixed with the name of the group it is in, if any.
+fn marker_label(index: usize, block: &dyn Block, group: &mut Option<String>) -> String {
+    if let Some(group_start) = block.as_any().downcast_ref::<GroupStart>() {
+        *group = Some(group_start.name());
+    }
+    let label = match group {
+        Some(name) => format!("{} / Block {}: {}", name, index + 1, block),
+        None => format!("Block {}: {}", index + 1, block),
+    };
+    if block.as_any().is::<GroupEnd>() {
+        *group = None;
+    }
+    return label;
+}
+
+/// Appends a `cue ` chunk with a cue point at each of the given sample offsets, and a `LIST` chunk of type `adtl`
+/// with a label for each cue point, to a finalized wav file.
+///
+/// Audio editors show these as markers, making block boundaries easy to find.
+fn write_markers(file_name: &Path, markers: &[(u32, String)]) -> io::Result<()> {
+    let mut cue = Vec::new();
+    cue.extend((markers.len() as u32).to_le_bytes());
+    for (id, (offset, _)) in (1u32..).zip(markers) {
+        cue.extend(id.to_le_bytes());
+        cue.extend(offset.to_le_bytes());
+        cue.extend(b"data");
+        cue.extend(0u32.to_le_bytes());
+        cue.extend(0u32.to_le_bytes());
+        cue.extend(offset.to_le_bytes());
+    }
+
+    let mut list = b"adtl".to_vec();
+    for (id, (_, label)) in (1u32..).zip(markers) {
+        let mut labl = id.to_le_bytes().to_vec();
+        labl.extend(label.as_bytes());
+        labl.push(0);
+        append_chunk(&mut list, b"labl", &labl);
+    }
+
+    let mut chunks = Vec::new();
+    append_chunk(&mut chunks, b"cue ", &cue);
+    append_chunk(&mut chunks, b"LIST", &list);
+
+    let mut file = OpenOptions::new().read(true).write(true).open(file_name)?;
+    let mut length = file.seek(SeekFrom::End(0))?;
+    // Chunks start on even offsets, so pad an odd length data chunk.
+    if length % 2 == 1 {
+        file.write_all(&[0])?;
+        length += 1;
+    }
+    file.write_all(&chunks)?;
+
+    // Update the RIFF chunk size to include the new chunks.
+    let riff_size = (length - 8) as u32 + chunks.len() as u32;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
     return Ok(());
 }
+
+/// Appends a RIFF chunk with the given id and data to a buffer, padded to an even length.
+fn append_chunk(buffer: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    buffer.extend(id);
+    buffer.extend((data.len() as u32).to_le_bytes());
+    buffer.extend(data);
+    if data.len() % 2 == 1 {
+        buffer.push(0);
+    }
+}
+
+/// Returns the wav format for the given arguments, checking that the bit depth is supported by the sample format.
+fn wav_spec(args: &ConvertArgs, config: &Config) -> io::Result<hound::WavSpec> {
+    let (sample_format, bits_per_sample) = match (args.sample_format, args.bit_depth) {
+        (WavSampleFormat::Int, None) => (hound::SampleFormat::Int, 16),
+        (WavSampleFormat::Int, Some(bits @ (8 | 16 | 24 | 32))) => (hound::SampleFormat::Int, bits),
+        (WavSampleFormat::Float, None | Some(32)) => (hound::SampleFormat::Float, 32),
+        (WavSampleFormat::Int, Some(bits)) => return Err(invalid_bit_depth(bits, "integer", "8, 16, 24 or 32")),
+        (WavSampleFormat::Float, Some(bits)) => return Err(invalid_bit_depth(bits, "float", "32")),
+    };
+
+    return Ok(hound::WavSpec {
+        channels: config.channels.count(),
+        sample_rate: config.sample_rate,
+        bits_per_sample,
+        sample_format,
+    });
+}
+
+fn invalid_bit_depth(bits: u16, sample_format: &str, supported: &str) -> io::Error {
+    let message = format!("A bit depth of {} is not supported for {} samples: use {}", bits, sample_format, supported);
+    return io::Error::new(ErrorKind::InvalidInput, message);
+}
+
+/// Quantises a sample to a signed integer with the given number of bits, rounding to the nearest value and clipping
+/// samples outside the range -1.0 to 1.0. Full scale is the largest positive value, so that positive and negative
+/// pulses have the same amplitude.
+///
+/// 8 bit samples are returned signed too: the wav writer offsets them to unsigned.
+fn quantise(sample: f32, bits: u16) -> i32 {
+    let max = ((1i64 << (bits - 1)) - 1) as f64;
+    return (sample as f64 * max).round().clamp(-max - 1.0, max) as i32;
+}
@@ -0,0 +1,120 @@
+use std::fs::File;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+use crate::{
+    TapeDataFile,
+    TapeDataFileType,
+    TzxData,
+};
+use crate::tzx::blocks::{
+    Block,
+    GeneralizedDataBlock,
+    GroupEnd,
+    GroupStart,
+    PauseOrStopTapeCommand,
+    TextDescription,
+    TurboSpeedDataBlock,
+};
+use crate::ui::commands::{
+    EditCommands,
+    EditConvertTo,
+    EditInsertArgs,
+};
+
+pub fn run_edit(command: &EditCommands, tape_data: &TapeDataFile) -> io::Result<()> {
+    if tape_data.file_type == TapeDataFileType::Tap {
+        return Err(invalid_input("Editing is only supported for tzx / cdt / tsx files".to_string()));
+    }
+
+    let mut tzx_data = tape_data.tzx_data.clone().expect("TZX data missing!");
+
+    match command {
+        EditCommands::Rm(args) => {
+            let indices = args.blocks.indices(tzx_data.blocks.len()).map_err(invalid_input)?;
+            tzx_data.blocks.drain(indices);
+            write_tzx(&tzx_data, args.output.output_file_name(&args.file.file_name))?;
+        }
+        EditCommands::Mv(args) => {
+            let from = block_index(args.from, tzx_data.blocks.len())?;
+            let to = block_index(args.to, tzx_data.blocks.len())?;
+            let block = tzx_data.blocks.remove(from);
+            tzx_data.blocks.insert(to, block);
+            write_tzx(&tzx_data, args.output.output_file_name(&args.file.file_name))?;
+        }
+        EditCommands::Insert(args) => {
+            // Allow the position one after the last block, for appending.
+            let position = block_index(args.position, tzx_data.blocks.len() + 1)?;
+            tzx_data.blocks.insert(position, insert_block(args));
+            write_tzx(&tzx_data, args.output.output_file_name(&args.file.file_name))?;
+        }
+        EditCommands::Extract(args) => {
+            let indices = args.blocks.indices(tzx_data.blocks.len()).map_err(invalid_input)?;
+            let extracted = TzxData {
+                header: tzx_data.header.clone(),
+                blocks: tzx_data.blocks[indices].to_vec(),
+            };
+            write_tzx(&extracted, &args.output_file_name)?;
+        }
+        EditCommands::Convert(args) => {
+            let indices = args.blocks.indices(tzx_data.blocks.len()).map_err(invalid_input)?;
+            for index in indices {
+                let block = &tzx_data.blocks[index];
+                let converted: Option<Box<dyn Block>> = match args.to {
+                    EditConvertTo::Generalized => block.as_any().downcast_ref::<TurboSpeedDataBlock>()
+                        .map(|turbo| Box::new(GeneralizedDataBlock::from(turbo)) as Box<dyn Block>),
+                    EditConvertTo::Turbo => match block.as_any().downcast_ref::<GeneralizedDataBlock>().map(TurboSpeedDataBlock::try_from) {
+                        Some(Ok(turbo)) => Some(Box::new(turbo)),
+                        Some(Err(why)) => {
+                            eprintln!("Block {}: cannot convert to a turbo speed data block: {}", index + 1, why);
+                            None
+                        }
+                        None => None,
+                    },
+                };
+                if let Some(converted) = converted {
+                    println!("Block {:3}: {}", index + 1, converted);
+                    tzx_data.blocks[index] = converted;
+                }
+            }
+            write_tzx(&tzx_data, args.output.output_file_name(&args.file.file_name))?;
+        }
+    }
+
+    return Ok(());
+}
+
+fn insert_block(args: &EditInsertArgs) -> Box<dyn Block> {
+    if let Some(pause) = args.pause {
+        Box::new(PauseOrStopTapeCommand::new(pause))
+    } else if args.stop_tape {
+        Box::new(PauseOrStopTapeCommand::new(0))
+    } else if let Some(text) = &args.text {
+        Box::new(TextDescription::new(text))
+    } else if let Some(name) = &args.group_start {
+        Box::new(GroupStart::new(name))
+    } else {
+        Box::new(GroupEnd::new())
+    }
+}
+
+/// Converts a block number as shown by inspect to a zero-based index, checking it against the number of blocks.
+fn block_index(number: usize, block_count: usize) -> io::Result<usize> {
+    if number == 0 || number > block_count {
+        return Err(invalid_input(format!("Block {} is out of bounds: there are {} blocks", number, block_count)));
+    }
+    return Ok(number - 1);
+}
+
+/// Writes TZX data to the given file.
+pub(crate) fn write_tzx(tzx_data: &TzxData, file_name: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(file_name)?);
+    tzx_data.write(&mut writer).map_err(|why| io::Error::other(why.to_string()))?;
+    // Flush explicitly, as errors are ignored when a BufWriter is flushed on drop.
+    writer.flush()?;
+    return Ok(());
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, message)
+}
@@ -30,7 +30,7 @@ pub fn run_inspect(path: &Path, config: &Config, waveforms: bool, tape_data: &Ta
                 block.extended_display(&mut printer);
 
                 if waveforms {
-                    let waveforms = block.get_waveforms(config.clone(), true);
+                    let waveforms = block.get_waveforms(config.for_block(index), true);
                     for waveform in waveforms {
                         println!("  Waveform: {}", waveform);
                     }
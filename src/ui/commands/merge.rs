@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::{
+    TapeDataFile,
+    TapeDataFileType,
+    TzxData,
+};
+use crate::tzx::{
+    Header,
+    ParseMode,
+    blocks::{
+        Block,
+        GroupEnd,
+        GroupStart,
+        PauseOrStopTapeCommand,
+        TextDescription,
+    },
+};
+use crate::ui::commands::{MergeArgs, MergeLabel, edit::write_tzx};
+
+pub fn run_merge(args: &MergeArgs, parse_mode: ParseMode) -> io::Result<()> {
+    let mut blocks: Vec<Box<dyn Block>> = Vec::new();
+    let mut version = (1, 0);
+
+    for (index, file_name) in args.file_names.iter().enumerate() {
+        let mut side = read_side(file_name, parse_mode)?;
+        side.remove_glue_blocks();
+        version = version.max(side.header.version());
+
+        if index > 0 && args.stop_between_sides {
+            blocks.push(Box::new(PauseOrStopTapeCommand::new(0)));
+        }
+
+        let name = file_name.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+        // Groups may not be nested, so sides that already contain groups are labelled with a text description.
+        let mut label = args.label;
+        if label == Some(MergeLabel::Group) && side.blocks.iter().any(|block| block.as_any().is::<GroupStart>()) {
+            eprintln!("Warning: {:?} contains groups: labelling with a text description instead", file_name);
+            label = Some(MergeLabel::Text);
+        }
+
+        match label {
+            Some(MergeLabel::Group) => {
+                blocks.push(Box::new(GroupStart::new(&name)));
+                blocks.extend(side.blocks);
+                blocks.push(Box::new(GroupEnd::new()));
+            }
+            Some(MergeLabel::Text) => {
+                blocks.push(Box::new(TextDescription::new(&name)));
+                blocks.extend(side.blocks);
+            }
+            None => blocks.extend(side.blocks),
+        }
+    }
+
+    let merged = TzxData { header: Header::new(version.0, version.1), blocks };
+
+    return write_tzx(&merged, &args.output_file_name);
+}
+
+/// Reads a tape file as TZX data, reporting any blocks skipped while parsing as warnings.
+fn read_side(file_name: &Path, parse_mode: ParseMode) -> io::Result<TzxData> {
+    let mut file = File::open(file_name)
+        .map_err(|why| io::Error::new(why.kind(), format!("Couldn't open {:?}: {}", file_name, why)))?;
+
+    let file_type = TapeDataFileType::from(file_name.extension().and_then(|s| s.to_str()));
+
+    let (tape_data, report) = TapeDataFile::parse_as(&mut file, file_type, parse_mode)
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, format!("Failed to parse {:?} as {}: {}", file_name, file_type, why)))?;
+
+    for diagnostic in &report.diagnostics {
+        eprintln!("Warning: {:?}: {} {}", file_name, diagnostic.outcome(), diagnostic);
+    }
+
+    return Ok(tape_data.tzx_data.expect("TZX data missing!"));
+}
@@ -0,0 +1,48 @@
+use std::io::{self, ErrorKind};
+
+use crate::{
+    TapeDataFile,
+    TapeDataFileType,
+};
+use crate::tzx::{
+    blocks::{Block, DirectRecording},
+    optimise::optimise_direct_recording,
+};
+use crate::ui::commands::{OptimiseArgs, edit::write_tzx};
+
+pub fn run_optimise(args: &OptimiseArgs, tape_data: &TapeDataFile) -> io::Result<()> {
+    if tape_data.file_type == TapeDataFileType::Tap {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Optimising is only supported for tzx / cdt / tsx files"));
+    }
+
+    let mut tzx_data = tape_data.tzx_data.clone().expect("TZX data missing!");
+
+    let mut blocks: Vec<Box<dyn Block>> = Vec::new();
+    let mut optimised_count = 0;
+    for (index, block) in tzx_data.blocks.iter().enumerate() {
+        let optimised = block.as_any().downcast_ref::<DirectRecording>()
+            .and_then(|direct_recording| optimise_direct_recording(direct_recording, args.tolerance_percent));
+
+        match optimised {
+            Some(replacement) => {
+                println!("Block {:3}: {}", index + 1, block);
+                for replacement_block in &replacement {
+                    println!("       -> {}", replacement_block);
+                }
+                optimised_count += 1;
+                blocks.extend(replacement);
+            }
+            None => blocks.push(block.clone()),
+        }
+    }
+
+    if optimised_count == 0 {
+        println!("No direct recordings could be optimised");
+        return Ok(());
+    }
+
+    tzx_data.blocks = blocks;
+    write_tzx(&tzx_data, args.output.output_file_name(&args.file.file_name))?;
+    println!("Optimised {} direct recordings", optimised_count);
+    return Ok(());
+}
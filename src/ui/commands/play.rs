@@ -1,7 +1,7 @@
 use cpal::traits::HostTrait;
 use cpal::{BufferSize, SampleFormat};
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{self, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{enable_raw_mode, disable_raw_mode},
     cursor,
@@ -9,24 +9,35 @@ use crossterm::{
 use figlet_rs::FIGfont;
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Layout},
+    layout::{Alignment, Constraint, Layout, Rect},
     prelude::Direction,
     style::{Color, Stylize},
     text::{Line, Span},
-    widgets::{Borders, Paragraph, Widget},
+    widgets::{Borders, Clear, Paragraph, Widget, Wrap},
     Terminal, TerminalOptions,
 };
-use std::io;
+use std::io::{self, ErrorKind};
 use std::path::Path;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::tzx::{
-    Config, ExtendedDisplayCollector, Player, TapeDataFile, waveforms::Waveform,
+    Config, ExtendedDisplayCollector, Player, TapeDataFile, blocks::MessageBlock, timing::{adjust_timings, block_waveforms},
+    waveforms::Waveform,
 };
+use crate::ui::block_list::browse_blocks;
 
-fn format_duration(duration: Duration) -> String {
+/// The change in amplitude in dB for each press of the volume keys.
+const VOLUME_STEP_DB: f32 = 1.0;
+
+/// The distance to seek for each press of the seek keys.
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// The change in playback duration adjustment in percentage points for each press of the duration keys.
+const DURATION_STEP_PERCENT: i32 = 1;
+
+pub(crate) fn format_duration(duration: Duration) -> String {
     let total_seconds = duration.as_secs();
     let minutes = total_seconds / 60;
     let seconds = total_seconds % 60;
@@ -35,6 +46,23 @@ fn format_duration(duration: Duration) -> String {
     format!("{:2}m {:02}s {:03}ms", minutes, seconds, milliseconds)
 }
 
+/// A message from a [MessageBlock] shown over the playback pane.
+pub struct MessagePopup {
+    pub text: String,
+    /// When the message should be removed, or `None` if it is shown until a key is pressed.
+    pub until: Option<Instant>,
+}
+
+impl MessagePopup {
+    fn new(message_block: &MessageBlock) -> Self {
+        let until = match message_block.display_for_secs() {
+            0 => None,
+            secs => Some(Instant::now() + Duration::from_secs(secs as u64)),
+        };
+        return Self { text: message_block.text(), until };
+    }
+}
+
 struct PlayLineCollector {
     pub lines: Vec<Line<'static>>,
 }
@@ -49,7 +77,10 @@ impl ExtendedDisplayCollector for PlayLineCollector {
 }
 
 pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::Result<()> {
-    let tzx_data = tape_data.tzx_data.as_ref().expect("TZX data missing!");
+    let tzx_data = &adjust_timings(tape_data.tzx_data.as_ref().expect("TZX data missing!"), config);
+    if block_waveforms(&tzx_data.blocks, &Arc::new(config.clone()), 0).all(|waveforms| waveforms.is_empty()) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Nothing to play: no blocks in the tape produce any output"));
+    }
 
     let default_device = cpal::default_host()
         .default_output_device()
@@ -98,11 +129,13 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
 
     let mut last_block_index: usize = 0;
 
-    let ui_title = Line::from(vec![
+    // The total duration changes with the playback speed, so the title is rebuilt when it does.
+    let title = |player: &Player| Line::from(vec![
         " Playback of ".green().bold(),
         format!("{}", path.display()).bold(),
         format!(" {} ", format_duration(player.total_duration)).yellow(),
     ]);
+    let mut ui_title = title(&player);
     let ui_instructions = Line::from(vec![
             " Pause / Play ".into(),
             "<SPACE>".blue().bold(),
@@ -111,13 +144,33 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
             " Rewind to previous block ".into(),
             "<←>".blue().bold().into(),
             " Skip to next block ".into(),
-            "<→> ".blue().bold().into(),
+            "<→>".blue().bold(),
+            " Seek 5s ".into(),
+            "<⇧←/⇧→>".blue().bold(),
+            " Pilot ".into(),
+            "<P>".blue().bold(),
+            " Blocks ".into(),
+            "<B>".blue().bold(),
+            " Group ".into(),
+            "<PGUP/PGDN>".blue().bold(),
+            " Volume ".into(),
+            "<↑/↓>".blue().bold(),
+            " Invert ".into(),
+            "<I>".blue().bold(),
+            " Duration ".into(),
+            "<+/->".blue().bold(),
+            " ".into(),
         ]);
 
     player.play();
 
     let mut playback_render = true;
 
+    // Message blocks produce no signal, so are never the current block. Instead their messages are shown once playback
+    // moves past them, but not when they are skipped over by seeking, which pauses playback.
+    let mut message: Option<MessagePopup> = None;
+    let mut message_check_index: usize = 0;
+
     // UI event loop
     loop {
         if playback_render {
@@ -125,7 +178,7 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
                 let (waveform_elapsed, _) = player.progress_in_current_waveform();
                 let _ = player.waveforms[player.current_waveform_index].try_seek(waveform_elapsed);
             }
-            render_playback_pane(&mut terminal, ui_title.clone(), ui_instructions.clone(), &player)?;
+            render_playback_pane(&mut terminal, ui_title.clone(), ui_instructions.clone(), &player, message.as_ref())?;
         }
 
         playback_render = !player.is_paused();
@@ -136,10 +189,42 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
                     break;
                 }
 
-                if key.is_press() {
+                if key.is_press() && message.is_some() {
+                    // Any key dismisses a message, without having its usual effect.
+                    message = None;
+                    playback_render = true;
+                } else if key.is_press() {
                     match key.code {
                         KeyCode::Char('q') => break,
                         KeyCode::Char(' ') => player.toggle_pause(),
+                        KeyCode::Left if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                            player.seek(player.elapsed().saturating_sub(SEEK_STEP));
+                            playback_render = true;
+                        },
+                        KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                            player.seek(player.elapsed() + SEEK_STEP);
+                            playback_render = true;
+                        },
+                        KeyCode::Char('p') => {
+                            player.seek_pilot();
+                            playback_render = true;
+                        },
+                        KeyCode::Char('b') => {
+                            if let Some(block_index) = browse_blocks(&mut player)? {
+                                player.seek_block(block_index);
+                            }
+                            // The inline viewport must be redrawn in full after the block list's screen.
+                            terminal.clear()?;
+                            playback_render = true;
+                        },
+                        KeyCode::PageUp => {
+                            player.seek_previous_group();
+                            playback_render = true;
+                        },
+                        KeyCode::PageDown => {
+                            player.seek_next_group();
+                            playback_render = true;
+                        },
                         KeyCode::Left => if player.current_block_index > 0 {
                             if player.block_durations[player.current_block_index - 1] == Duration::ZERO && player.current_block_index > 1 {
                                 player.seek_block(player.current_block_index - 2);
@@ -152,6 +237,29 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
                             player.seek_block(player.current_block_index + 1);
                             playback_render = true;
                         },
+                        KeyCode::Up => {
+                            player.levels.set_amplitude_db(player.levels.amplitude_db() + VOLUME_STEP_DB);
+                            playback_render = true;
+                        },
+                        KeyCode::Down => {
+                            player.levels.set_amplitude_db(player.levels.amplitude_db() - VOLUME_STEP_DB);
+                            playback_render = true;
+                        },
+                        KeyCode::Char('i') => {
+                            player.levels.set_invert(!player.levels.invert());
+                            playback_render = true;
+                        },
+                        // As for --playback-duration-percent, '+' slows playback down. '=' is accepted as '+' without shift.
+                        KeyCode::Char('+') | KeyCode::Char('=') => {
+                            player.change_playback_duration_percent(DURATION_STEP_PERCENT);
+                            ui_title = title(&player);
+                            playback_render = true;
+                        },
+                        KeyCode::Char('-') => {
+                            player.change_playback_duration_percent(-DURATION_STEP_PERCENT);
+                            ui_title = title(&player);
+                            playback_render = true;
+                        },
                         _ => (),
                     }
                 }
@@ -160,6 +268,22 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
 
         player.tick();
 
+        if player.is_paused() {
+            message_check_index = player.current_block_index;
+        } else {
+            let reached_index = if player.is_finished() { player.len_blocks() } else { player.current_block_index };
+            for index in message_check_index..reached_index {
+                if let Some(message_block) = player.blocks[index].as_any().downcast_ref::<MessageBlock>() {
+                    message = Some(MessagePopup::new(message_block));
+                }
+            }
+            message_check_index = message_check_index.max(reached_index);
+        }
+        if let Some(MessagePopup { until: Some(until), .. }) = message && Instant::now() >= until {
+            message = None;
+            playback_render = true;
+        }
+
         while last_block_index < player.current_block_index {
             let last_block_text = Line::from(vec![
                 "Block ".into(),
@@ -184,7 +308,7 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
             last_block_index += 1;
         }
 
-        if !player.is_paused() && player.is_finished() {
+        if !player.is_paused() && player.is_finished() && message.is_none() {
             break;
         }
 
@@ -205,7 +329,19 @@ pub fn run_play(path: &Path, config: &Config, tape_data: &TapeDataFile) -> io::R
     return Ok(());
 }
 
-pub fn render_playback_pane(terminal: &mut Terminal<CrosstermBackend<&mut std::io::Stdout>>, ui_title: Line<'_>, ui_instructions: Line<'_>, player: &Player) -> io::Result<()> {
+/// Returns the playback duration adjustment of the current block, followed by that of the next block if it has been
+/// changed since the current block started.
+fn duration_percent_text(player: &Player) -> String {
+    let index = player.current_block_index;
+    let percent = player.playback_duration_percent(index);
+    let next_percent = player.playback_duration_percent(index + 1);
+    if index + 1 < player.len_blocks() && next_percent != percent {
+        return format!("{:+}% (next {:+}%)", percent, next_percent);
+    }
+    return format!("{:+}%", percent);
+}
+
+pub fn render_playback_pane(terminal: &mut Terminal<CrosstermBackend<&mut std::io::Stdout>>, ui_title: Line<'_>, ui_instructions: Line<'_>, player: &Player, message: Option<&MessagePopup>) -> io::Result<()> {
     let blocks_count = player.len_blocks();
     let (waveform_elapsed, waveform_duration) = player.progress_in_current_waveform();
     let (block_elapsed, block_duration) = player.progress_in_current_block();
@@ -220,12 +356,21 @@ pub fn render_playback_pane(terminal: &mut Terminal<CrosstermBackend<&mut std::i
             ])
             .split(area);
 
+        let group_names: Vec<String> = player.current_groups().iter().map(|group| group.name.clone()).collect();
         let block = ratatui::widgets::Block::default()
             .title(ui_title)
+            .title(if group_names.is_empty() {
+                Line::default()
+            } else {
+                Line::from(vec![" Group: ".into(), format!("{} ", group_names.join(" / ")).bold()]).right_aligned()
+            })
             .title_bottom(ui_instructions.centered())
             .borders(Borders::ALL);
 
-        let waveform_data_line = render_waveform_data_line(&player.waveforms[player.current_waveform_index]);
+        let waveform_data_line = render_waveform_data_line(
+            &player.waveforms[player.current_waveform_index],
+            player.playback_duration_percent(player.current_block_index),
+        );
 
         let content = vec![
             Line::from(vec![
@@ -244,23 +389,52 @@ pub fn render_playback_pane(terminal: &mut Terminal<CrosstermBackend<&mut std::i
                 "W: ".into(), format!("{:10}  / {:10}   ", format_duration(waveform_elapsed), format_duration(waveform_duration.saturating_sub(waveform_elapsed))).yellow(),
                 "B: ".into(), format!("{:10}  / {:10}   ", format_duration(block_elapsed), format_duration(block_duration.saturating_sub(block_elapsed))).yellow(),
                 "T: ".into(), format!("{:10}  / {:10}   ", format_duration(player.elapsed()), format_duration(player.total_duration.saturating_sub(player.elapsed()))).yellow(),
+                "Vol: ".into(), format!("{:+4.0} dB{}   ", player.levels.amplitude_db(), if player.levels.invert() { " inverted" } else { "" }).yellow(),
+                "Dur: ".into(), format!("{}   ", duration_percent_text(player)).yellow(),
                 if player.is_paused() { "** PAUSED **".bold() } else { "".into() }
             ]),
         ];
 
         f.render_widget(Paragraph::new(content).block(block), chunks[1]);
+
+        if let Some(message) = message {
+            let popup_area = Rect {
+                x: chunks[1].x + chunks[1].width / 6,
+                width: chunks[1].width - chunks[1].width / 3,
+                ..chunks[1]
+            };
+            let remaining = match message.until {
+                Some(until) => format!(" {}s ", until.saturating_duration_since(Instant::now()).as_secs() + 1),
+                None => " Press any key ".to_string(),
+            };
+            let popup = Paragraph::new(message.text.as_str())
+                .bold()
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true })
+                .block(ratatui::widgets::Block::default()
+                    .title(" Message ".bold())
+                    .title_bottom(Line::from(remaining).centered())
+                    .borders(Borders::ALL))
+                .style(ratatui::style::Style::default().fg(Color::Black).bg(Color::Yellow));
+            f.render_widget(Clear, popup_area);
+            f.render_widget(popup, popup_area);
+        }
     })?;
 
     return Ok(());
 }
 
-fn render_waveform_data_line(waveform: &Box<dyn Waveform + Send>) -> Line<'_> {
+fn render_waveform_data_line(waveform: &Box<dyn Waveform + Send>, playback_duration_percent: i32) -> Line<'_> {
     let mut spans: Vec<Span<'_>> = Vec::new();
     spans.push(format!("{}", waveform.visualise(64)).fg(Color::LightYellow).bg(Color::LightBlue));
 
+    // The baud is measured from the waveform, so already reflects any playback duration adjustment.
     if let Some(baud) = waveform.current_baud() {
         spans.push(format!("  {:4}", baud).bold());
         spans.push(" baud".into());
+        if playback_duration_percent != 0 {
+            spans.push(format!(" ({:+}%)", playback_duration_percent).yellow());
+        }
     }
 
     if let Some(payload_with_position) = waveform.payload_with_position() {
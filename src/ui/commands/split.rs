@@ -0,0 +1,51 @@
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    TapeDataFile,
+    TapeDataFileType,
+};
+use crate::tzx::SplitPoints;
+use crate::ui::commands::{SplitArgs, SplitBy, edit::write_tzx};
+
+pub fn run_split(args: &SplitArgs, tape_data: &TapeDataFile) -> io::Result<()> {
+    if tape_data.file_type == TapeDataFileType::Tap {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Splitting is only supported for tzx / cdt / tsx files"));
+    }
+
+    let tzx_data = tape_data.tzx_data.as_ref().expect("TZX data missing!");
+
+    let split_points = match args.by {
+        Some(SplitBy::Stop) => SplitPoints::StopTape,
+        Some(SplitBy::Group) => SplitPoints::GroupStart,
+        None => {
+            if let Some(number) = args.at.iter().find(|number| **number == 0 || **number > tzx_data.blocks.len()) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Block {} is out of bounds: there are {} blocks", number, tzx_data.blocks.len()),
+                ));
+            }
+            SplitPoints::Blocks(args.at.iter().map(|number| number - 1).collect())
+        }
+    };
+
+    let pieces = tzx_data.split(&split_points);
+    let output_file_name = args.output_file_name.as_ref().unwrap_or(&args.file.file_name);
+    let width = pieces.len().to_string().len();
+
+    for (index, piece) in pieces.iter().enumerate() {
+        let file_name = numbered_file_name(output_file_name, index + 1, width, tape_data.file_type);
+        write_tzx(piece, &file_name)?;
+        println!("{}: {} blocks", file_name.display(), piece.blocks.len());
+    }
+
+    return Ok(());
+}
+
+/// Returns the given filename with a zero padded number appended to the file stem, e.g. `out-01.tzx`. The extension
+/// of the supplied filename is kept, or that of the file type used if there is none.
+pub(crate) fn numbered_file_name(file_name: &Path, number: usize, width: usize, file_type: TapeDataFileType) -> PathBuf {
+    let stem = file_name.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = file_name.extension().map(|s| s.to_string_lossy().to_string()).unwrap_or(file_type.to_string());
+    return file_name.with_file_name(format!("{}-{:0width$}.{}", stem, number, extension, width = width));
+}